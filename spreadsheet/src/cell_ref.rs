@@ -0,0 +1,277 @@
+use crate::parser::cell::{Cell, convert_string_to_cell};
+use crate::parser::print_output::column_number_to_letters;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Error returned when a cell or range in A1 notation cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefError {
+    InvalidCell(String),
+    InvalidRange(String),
+}
+
+impl fmt::Display for RefError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RefError::InvalidCell(cell) => write!(f, "invalid cell reference `{}`", cell),
+            RefError::InvalidRange(range) => write!(f, "invalid range `{}`", range),
+        }
+    }
+}
+
+impl std::error::Error for RefError {}
+
+/// A reference to a single cell. Stored 0-indexed, written in A1 notation, also when
+/// serialized. Ordering is row-major: `A1 < B1 < A2`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde_derive::Serialize,
+    serde_derive::Deserialize,
+)]
+#[serde(into = "String", try_from = "String")]
+pub struct CellRef {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl CellRef {
+    pub fn new(row: usize, col: usize) -> Self {
+        CellRef { row, col }
+    }
+}
+
+impl From<(usize, usize)> for CellRef {
+    fn from((row, col): (usize, usize)) -> Self {
+        CellRef { row, col }
+    }
+}
+
+impl From<CellRef> for (usize, usize) {
+    fn from(cell: CellRef) -> Self {
+        (cell.row, cell.col)
+    }
+}
+
+/// Converts a 1-indexed parser token into a 0-indexed reference
+impl From<Cell> for CellRef {
+    fn from(cell: Cell) -> Self {
+        CellRef {
+            row: cell.row - 1,
+            col: cell.col - 1,
+        }
+    }
+}
+
+impl FromStr for CellRef {
+    type Err = RefError;
+
+    /// Parses A1 notation. Columns go up to `ZZZ`, rows start at 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RefError::InvalidCell(s.to_string());
+        let letters = s.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+        if letters == 0 || letters > 3 || !s[..letters].chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(invalid());
+        }
+        if !s[letters..].chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        match convert_string_to_cell(s) {
+            Some(cell) if cell.row >= 1 => Ok(CellRef::from(cell)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for CellRef {
+    type Error = RefError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<CellRef> for String {
+    fn from(cell: CellRef) -> Self {
+        cell.to_string()
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            column_number_to_letters(self.col + 1),
+            self.row + 1
+        )
+    }
+}
+
+/// A rectangular range of cells from `start` (top-left) to `end` (bottom-right), inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RangeRef {
+    pub start: CellRef,
+    pub end: CellRef,
+}
+
+impl RangeRef {
+    /// Returns `None` if `start` is not above and to the left of `end`
+    pub fn new(start: CellRef, end: CellRef) -> Option<Self> {
+        (start.row <= end.row && start.col <= end.col).then_some(RangeRef { start, end })
+    }
+
+    pub fn contains(&self, cell: CellRef) -> bool {
+        (self.start.row..=self.end.row).contains(&cell.row)
+            && (self.start.col..=self.end.col).contains(&cell.col)
+    }
+
+    /// Number of cells in the range
+    pub fn len(&self) -> usize {
+        (self.end.row - self.start.row + 1) * (self.end.col - self.start.col + 1)
+    }
+
+    /// A range always holds at least one cell
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Iterates over the cells of the range in row-major order
+    pub fn cells(&self) -> RangeIter {
+        RangeIter {
+            range: *self,
+            next: Some(self.start),
+        }
+    }
+}
+
+impl From<CellRef> for RangeRef {
+    fn from(cell: CellRef) -> Self {
+        RangeRef {
+            start: cell,
+            end: cell,
+        }
+    }
+}
+
+impl FromStr for RangeRef {
+    type Err = RefError;
+
+    /// Parses `A1:C3`, or a single cell `A1` as a one-cell range
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once(':').unwrap_or((s, s));
+        let start = start.parse::<CellRef>()?;
+        let end = end.parse::<CellRef>()?;
+        RangeRef::new(start, end).ok_or_else(|| RefError::InvalidRange(s.to_string()))
+    }
+}
+
+impl fmt::Display for RangeRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.start, self.end)
+    }
+}
+
+/// Row-major iterator over the cells of a [`RangeRef`]
+pub struct RangeIter {
+    range: RangeRef,
+    next: Option<CellRef>,
+}
+
+impl Iterator for RangeIter {
+    type Item = CellRef;
+
+    fn next(&mut self) -> Option<CellRef> {
+        let current = self.next?;
+        self.next = if current.col < self.range.end.col {
+            Some(CellRef::new(current.row, current.col + 1))
+        } else if current.row < self.range.end.row {
+            Some(CellRef::new(current.row + 1, self.range.start.col))
+        } else {
+            None
+        };
+        Some(current)
+    }
+}
+
+impl IntoIterator for RangeRef {
+    type Item = CellRef;
+    type IntoIter = RangeIter;
+
+    fn into_iter(self) -> RangeIter {
+        self.cells()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_ref_parse_and_display() {
+        let cell: CellRef = "AA10".parse().unwrap();
+        assert_eq!(cell, CellRef::new(9, 26));
+        assert_eq!(cell.to_string(), "AA10");
+        assert_eq!("A1".parse::<CellRef>(), Ok(CellRef::new(0, 0)));
+        assert_eq!(CellRef::new(0, 18277).to_string(), "ZZZ1");
+    }
+
+    #[test]
+    fn test_cell_ref_parse_invalid() {
+        for s in ["", "A", "1", "A0", "a1", "A-1", "A1B", "AAAA1", "A 1"] {
+            assert_eq!(
+                s.parse::<CellRef>(),
+                Err(RefError::InvalidCell(s.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_cell_ref_ordering_is_row_major() {
+        let mut cells: Vec<CellRef> = ["A2", "B1", "A1"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        cells.sort();
+        let names: Vec<String> = cells.iter().map(|c| c.to_string()).collect();
+        assert_eq!(names, ["A1", "B1", "A2"]);
+    }
+
+    #[test]
+    fn test_cell_ref_from_parser_cell() {
+        assert_eq!(CellRef::from(Cell { row: 1, col: 2 }), CellRef::new(0, 1));
+    }
+
+    #[test]
+    fn test_range_ref_parse() {
+        let range: RangeRef = "B2:C3".parse().unwrap();
+        assert_eq!(range.start, CellRef::new(1, 1));
+        assert_eq!(range.end, CellRef::new(2, 2));
+        assert_eq!(range.to_string(), "B2:C3");
+        assert_eq!(range.len(), 4);
+        assert_eq!(
+            "A1".parse::<RangeRef>(),
+            Ok(RangeRef::from(CellRef::new(0, 0)))
+        );
+        assert_eq!(
+            "C3:B2".parse::<RangeRef>(),
+            Err(RefError::InvalidRange("C3:B2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_range_ref_iteration() {
+        let range: RangeRef = "A1:B2".parse().unwrap();
+        let names: Vec<String> = range.into_iter().map(|c| c.to_string()).collect();
+        assert_eq!(names, ["A1", "B1", "A2", "B2"]);
+        assert!(range.contains("B2".parse().unwrap()));
+        assert!(!range.contains("C1".parse().unwrap()));
+    }
+}
@@ -1,3 +1,5 @@
+use super::cell_ref::CellRef;
+use super::error::SheetError;
 use super::spreadsheet::SpreadSheet;
 use super::utils::Coordinate;
 use super::utils::Type;
@@ -29,10 +31,11 @@ impl Equation {
         operands: Option<Vec<SharedOperand>>,
     ) -> Self {
         let t = t.unwrap_or(Type::Nul);
+        // Missing operands are reported when the equation is processed
         let operands = if t == Type::Nul {
             Vec::<SharedOperand>::new()
         } else {
-            operands.expect("Operands cannot be None when Type is not NUL")
+            operands.unwrap_or_default()
         };
 
         Equation {
@@ -46,10 +49,35 @@ impl Equation {
         &self.operands
     }
 
+    /// Returns the values of all the cells in the range given by the two operands
+    fn range_values(&self, spreadsheet_ref: &SpreadSheet) -> Result<Vec<Option<i32>>, SheetError> {
+        let cell = CellRef::new(self.coordinate.0, self.coordinate.1);
+        let [start, end] = &self.operands[..] else {
+            return Err(SheetError::InvalidOperands(cell));
+        };
+        let (start, end) = (start.borrow(), end.borrow());
+        if !start.is_cell() || !end.is_cell() {
+            return Err(SheetError::InvalidOperands(cell));
+        }
+        let Coordinate(y1, x1) = *start.get_coordinate();
+        let Coordinate(y2, x2) = *end.get_coordinate();
+        if x1 > x2 || y1 > y2 {
+            return Err(SheetError::InvalidRange(cell));
+        }
+
+        let mut values = Vec::with_capacity((y2 - y1 + 1) * (x2 - x1 + 1));
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                values.push(spreadsheet_ref.get_cell_value(y, x)?);
+            }
+        }
+        Ok(values)
+    }
+
     pub fn process_equation_silent(
         &self,
         spreadsheet_ref: &SpreadSheet,
-    ) -> (Option<i32>, Option<i32>) {
+    ) -> Result<(Option<i32>, Option<i32>), SheetError> {
         // println!("Processing equation silent: ");
         // self.print();
 
@@ -58,146 +86,102 @@ impl Equation {
         // operand is None means that it is an ERR cell
         // second ret value is time to sleep
 
+        let cell = CellRef::new(self.coordinate.0, self.coordinate.1);
         let t = self.t;
         if t == Type::Slp {
-            let c = self.operands[0].borrow().get_value();
-
-            if c.is_none() {
-                return (None, None);
-            }
-            assert!(c.unwrap() >= 0, "Invalid negative sleep time");
-            // do nothing
-            return (c, c);
+            let [operand] = &self.operands[..] else {
+                return Err(SheetError::InvalidOperands(cell));
+            };
+            return match operand.borrow().get_value() {
+                None => Ok((None, None)),
+                Some(c) if c < 0 => Err(SheetError::NegativeSleep(cell)),
+                // do nothing
+                Some(c) => Ok((Some(c), Some(c))),
+            };
         }
 
         let operands = &self.operands;
         if operands.is_empty() {
-            return (Some(0), None);
-        }
-        let v1 = operands[0].borrow().get_value();
-        if v1.is_none() {
-            return (None, None);
+            return Ok((Some(0), None));
         }
-        let v1 = v1.unwrap();
-
-        let v2 = operands[1].borrow().get_value();
-        if v2.is_none() {
-            return (None, None);
-        }
-        let v2 = v2.unwrap();
-
-        match t {
-            Type::Add => (Some(v1 + v2), None),
-            Type::Sub => (Some(v1 - v2), None),
-            Type::Mul => (Some(v1 * v2), None),
-            Type::Div => match v2 {
-                0 => (None, None),
-                _ => (Some(v1 / v2), None),
-            },
-            Type::Min => {
-                let y1 = operands[0].borrow().get_coordinate().0;
-                let x1 = operands[0].borrow().get_coordinate().1;
-                let y2 = operands[1].borrow().get_coordinate().0;
-                let x2 = operands[1].borrow().get_coordinate().1;
-
-                assert!(x1 <= x2 && y1 <= y2, "Invalid range!");
-                let mut min = i32::MAX;
-                for y in y1..=y2 {
-                    for x in x1..=x2 {
-                        min = min.min(spreadsheet_ref.get_cell_value(y, x).unwrap_or(i32::MAX));
-                    }
-                }
-                (Some(min), None)
-            }
-            Type::Max => {
-                let y1 = operands[0].borrow().get_coordinate().0;
-                let x1 = operands[0].borrow().get_coordinate().1;
-                let y2 = operands[1].borrow().get_coordinate().0;
-                let x2 = operands[1].borrow().get_coordinate().1;
-
-                assert!(x1 <= x2 && y1 <= y2, "Invalid range!");
-                let mut max = i32::MIN;
-                for y in y1..=y2 {
-                    for x in x1..=x2 {
-                        max = max.max(spreadsheet_ref.get_cell_value(y, x).unwrap_or(i32::MIN));
-                    }
-                }
-                (Some(max), None)
-            }
+        let [operand_1, operand_2] = &operands[..] else {
+            return Err(SheetError::InvalidOperands(cell));
+        };
+        let Some(v1) = operand_1.borrow().get_value() else {
+            return Ok((None, None));
+        };
+        let Some(v2) = operand_2.borrow().get_value() else {
+            return Ok((None, None));
+        };
 
+        // Overflows and division by zero make the cell an ERR cell
+        let value = match t {
+            Type::Add => v1.checked_add(v2),
+            Type::Sub => v1.checked_sub(v2),
+            Type::Mul => v1.checked_mul(v2),
+            Type::Div => v1.checked_div(v2),
+            Type::Min => self
+                .range_values(spreadsheet_ref)?
+                .iter()
+                .map(|v| v.unwrap_or(i32::MAX))
+                .min(),
+            Type::Max => self
+                .range_values(spreadsheet_ref)?
+                .iter()
+                .map(|v| v.unwrap_or(i32::MIN))
+                .max(),
             Type::Sum => {
-                let y1 = operands[0].borrow().get_coordinate().0;
-                let x1 = operands[0].borrow().get_coordinate().1;
-                let y2 = operands[1].borrow().get_coordinate().0;
-                let x2 = operands[1].borrow().get_coordinate().1;
-
-                assert!(x1 <= x2 && y1 <= y2, "Invalid range!");
-                let mut sum = 0;
-                for y in y1..=y2 {
-                    for x in x1..=x2 {
-                        sum += spreadsheet_ref.get_cell_value(y, x).unwrap_or(0);
-                    }
-                }
-                (Some(sum), None)
+                let values = self.range_values(spreadsheet_ref)?;
+                let sum: i64 = values.iter().map(|v| v.unwrap_or(0) as i64).sum();
+                i32::try_from(sum).ok()
             }
             Type::Avg => {
-                let y1 = operands[0].borrow().get_coordinate().0;
-                let x1 = operands[0].borrow().get_coordinate().1;
-                let y2 = operands[1].borrow().get_coordinate().0;
-                let x2 = operands[1].borrow().get_coordinate().1;
-
-                assert!(x1 <= x2 && y1 <= y2, "Invalid range!");
-                let mut count = 0;
-                let mut sum = 0;
-                for y in y1..=y2 {
-                    for x in x1..=x2 {
-                        let v = spreadsheet_ref.get_cell_value(y, x);
-                        sum += v.unwrap_or(0);
-                        count += if v.is_some() { 1 } else { 0 };
-                    }
+                let values = self.range_values(spreadsheet_ref)?;
+                let count = values.iter().flatten().count() as i64;
+                let sum: i64 = values.iter().flatten().map(|&v| v as i64).sum();
+                if count == 0 {
+                    None
+                } else {
+                    i32::try_from(sum / count).ok()
                 }
-                (Some(sum / count), None)
             }
             Type::Dev => {
-                let y1 = operands[0].borrow().get_coordinate().0;
-                let x1 = operands[0].borrow().get_coordinate().1;
-                let y2 = operands[1].borrow().get_coordinate().0;
-                let x2 = operands[1].borrow().get_coordinate().1;
-
-                assert!(x1 <= x2 && y1 <= y2, "Invalid range!");
-                let mut count = 0;
-                let mut sum = 0;
-                let mut sq = 0;
-                for y in y1..=y2 {
-                    for x in x1..=x2 {
-                        let v = spreadsheet_ref.get_cell_value(y, x);
-                        sum += v.unwrap_or(0);
-                        count += if v.is_some() { 1 } else { 0 };
-                        sq += v.unwrap_or(0) * v.unwrap_or(0);
-                    }
+                let values = self.range_values(spreadsheet_ref)?;
+                let count = values.iter().flatten().count() as f64;
+                let sum: f64 = values.iter().flatten().map(|&v| v as f64).sum();
+                let sq: f64 = values
+                    .iter()
+                    .flatten()
+                    .map(|&v| (v as f64) * (v as f64))
+                    .sum();
+                if count == 0.0 {
+                    None
+                } else {
+                    let mean = sum / count;
+                    let mean_sq = sq / count;
+                    let std = (mean_sq - mean * mean).sqrt();
+                    Some(std as i32)
                 }
-                let mean = sum as f64 / count as f64;
-                let mean_sq = sq as f64 / count as f64;
-                let std = (mean_sq - mean * mean).sqrt();
-                (Some(std as i32), None)
             }
-            _ => {
-                panic!("Unsupported operation to process equation");
-            }
-        }
+            Type::Nul | Type::Slp => return Err(SheetError::InvalidOperands(cell)),
+        };
+        Ok((value, None))
     }
 
-    pub fn process_equation(&self, spreadsheet_ref: &SpreadSheet) -> Option<i32> {
+    pub fn process_equation(
+        &self,
+        spreadsheet_ref: &SpreadSheet,
+    ) -> Result<Option<i32>, SheetError> {
         // println!("Processing equation: ");
         if self.operands.is_empty() {
-            return Some(0);
+            return Ok(Some(0));
         }
 
-        let (val, sleep_time) = self.process_equation_silent(spreadsheet_ref);
+        let (val, sleep_time) = self.process_equation_silent(spreadsheet_ref)?;
         if let Some(sleep_time) = sleep_time {
-            sleep(Duration::from_millis((sleep_time * 1000) as u64));
+            sleep(Duration::from_secs(sleep_time as u64));
         }
-        val
+        Ok(val)
     }
 
     // pub fn print(&self){
@@ -245,7 +229,7 @@ mod tests {
 
         let spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(8), None)
         );
     }
@@ -262,7 +246,7 @@ mod tests {
 
         let spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(6), None)
         );
     }
@@ -279,7 +263,7 @@ mod tests {
 
         let spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(42), None)
         );
     }
@@ -296,7 +280,7 @@ mod tests {
 
         let spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(5), None)
         );
     }
@@ -312,7 +296,10 @@ mod tests {
         );
 
         let spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
-        assert_eq!(equation.process_equation_silent(&spreadsheet), (None, None));
+        assert_eq!(
+            equation.process_equation_silent(&spreadsheet).unwrap(),
+            (None, None)
+        );
     }
 
     #[test]
@@ -326,13 +313,13 @@ mod tests {
         );
 
         let mut spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
-        spreadsheet._set_cell_value(0, 0, 10);
-        spreadsheet._set_cell_value(0, 1, 5);
-        spreadsheet._set_cell_value(1, 0, 3);
-        spreadsheet._set_cell_value(1, 1, 8);
+        spreadsheet._set_cell_value(0, 0, 10).unwrap();
+        spreadsheet._set_cell_value(0, 1, 5).unwrap();
+        spreadsheet._set_cell_value(1, 0, 3).unwrap();
+        spreadsheet._set_cell_value(1, 1, 8).unwrap();
 
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(3), None)
         );
     }
@@ -348,13 +335,13 @@ mod tests {
         );
 
         let mut spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
-        spreadsheet._set_cell_value(0, 0, 10);
-        spreadsheet._set_cell_value(0, 1, 5);
-        spreadsheet._set_cell_value(1, 0, 3);
-        spreadsheet._set_cell_value(1, 1, 8);
+        spreadsheet._set_cell_value(0, 0, 10).unwrap();
+        spreadsheet._set_cell_value(0, 1, 5).unwrap();
+        spreadsheet._set_cell_value(1, 0, 3).unwrap();
+        spreadsheet._set_cell_value(1, 1, 8).unwrap();
 
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(10), None)
         );
     }
@@ -370,13 +357,13 @@ mod tests {
         );
 
         let mut spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
-        spreadsheet._set_cell_value(0, 0, 10);
-        spreadsheet._set_cell_value(0, 1, 5);
-        spreadsheet._set_cell_value(1, 0, 3);
-        spreadsheet._set_cell_value(1, 1, 8);
+        spreadsheet._set_cell_value(0, 0, 10).unwrap();
+        spreadsheet._set_cell_value(0, 1, 5).unwrap();
+        spreadsheet._set_cell_value(1, 0, 3).unwrap();
+        spreadsheet._set_cell_value(1, 1, 8).unwrap();
 
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(26), None)
         );
     }
@@ -392,13 +379,13 @@ mod tests {
         );
 
         let mut spreadsheet = SpreadSheet::new(10, 10); // Mock or real implementation
-        spreadsheet._set_cell_value(0, 0, 10);
-        spreadsheet._set_cell_value(0, 1, 5);
-        spreadsheet._set_cell_value(1, 0, 3);
-        spreadsheet._set_cell_value(1, 1, 8);
+        spreadsheet._set_cell_value(0, 0, 10).unwrap();
+        spreadsheet._set_cell_value(0, 1, 5).unwrap();
+        spreadsheet._set_cell_value(1, 0, 3).unwrap();
+        spreadsheet._set_cell_value(1, 1, 8).unwrap();
 
         assert_eq!(
-            equation.process_equation_silent(&spreadsheet),
+            equation.process_equation_silent(&spreadsheet).unwrap(),
             (Some(6), None)
         );
     }
@@ -0,0 +1,51 @@
+use crate::cell_ref::{CellRef, RefError};
+
+use std::fmt;
+
+/// Errors returned by the high-level [`SpreadSheet`](crate::SpreadSheet) API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SheetError {
+    /// The cell or range is not valid A1 notation
+    InvalidReference(RefError),
+    /// The cell lies outside the spreadsheet
+    OutOfBounds(CellRef),
+    /// The formula could not be parsed
+    InvalidFormula(String),
+    /// Setting the formula would make the cell depend on itself
+    Cycle(CellRef),
+    /// A function or operator name the engine does not know
+    UnknownFunction(String),
+    /// The operands of the cell's equation do not fit its type,
+    /// e.g. a second operand for `SLEEP` or a constant bound for `SUM`
+    InvalidOperands(CellRef),
+    /// The cell's range does not go from top-left to bottom-right
+    InvalidRange(CellRef),
+    /// The cell sleeps for a negative amount of time
+    NegativeSleep(CellRef),
+    /// A constant was used where a cell is required
+    NotACell,
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetError::InvalidReference(err) => write!(f, "{}", err),
+            SheetError::OutOfBounds(cell) => write!(f, "cell {} is outside the spreadsheet", cell),
+            SheetError::InvalidFormula(formula) => write!(f, "invalid formula `{}`", formula),
+            SheetError::Cycle(cell) => write!(f, "formula for {} creates a cycle", cell),
+            SheetError::UnknownFunction(name) => write!(f, "unknown function `{}`", name),
+            SheetError::InvalidOperands(cell) => write!(f, "invalid operands for {}", cell),
+            SheetError::InvalidRange(cell) => write!(f, "invalid range in {}", cell),
+            SheetError::NegativeSleep(cell) => write!(f, "{} sleeps for a negative time", cell),
+            SheetError::NotACell => write!(f, "expected a cell, found a constant"),
+        }
+    }
+}
+
+impl std::error::Error for SheetError {}
+
+impl From<RefError> for SheetError {
+    fn from(err: RefError) -> Self {
+        SheetError::InvalidReference(err)
+    }
+}
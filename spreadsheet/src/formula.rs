@@ -0,0 +1,219 @@
+use crate::cell_ref::{CellRef, RangeRef};
+use crate::error::SheetError;
+use crate::parser::cell::Value;
+use crate::parser::command::Command;
+use crate::parser::command_parser::parse_cmd;
+use crate::spreadsheet::Operand;
+use crate::utils::Type;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A parsed cell formula, e.g. `A1*3`, `42`, `SUM(A1:B2)` or `SLEEP(2)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Formula {
+    /// `operand_1 t operand_2`, or `SLEEP(operand_1)` when `t` is `Type::Slp`
+    Operation {
+        t: Type,
+        operand_1: Operand,
+        operand_2: Option<Operand>,
+    },
+    /// A range function (`SUM`, `AVG`, `MIN`, `MAX`, `STDEV`)
+    Range { t: Type, range: RangeRef },
+}
+
+impl Formula {
+    /// Splits a parsed cell command into its target cell and formula.
+    /// Returns `None` for commands that do not update a cell.
+    pub fn from_command(command: Command) -> Option<(CellRef, Formula)> {
+        let operand = |value: Value| match value {
+            Value::Cell(cell) => Operand::Cell(CellRef::from(cell)),
+            Value::Constant(constant) => Operand::Constant(constant),
+        };
+        match command {
+            Command::Range(cmd) => {
+                let (Value::Cell(cell), Value::Cell(operand_1), Value::Cell(operand_2)) =
+                    (cmd.target_cell, cmd.operand_1, cmd.operand_2)
+                else {
+                    return None;
+                };
+                let range = RangeRef::new(CellRef::from(operand_1), CellRef::from(operand_2))?;
+                let t = cmd.function.parse::<Type>().ok()?;
+                Some((CellRef::from(cell), Formula::Range { t, range }))
+            }
+            Command::Arithmetic(cmd) => {
+                let Value::Cell(cell) = cmd.target_cell else {
+                    return None;
+                };
+                let t = match cmd.operator {
+                    Some(op) => op.parse::<Type>().ok()?,
+                    None => Type::Add,
+                };
+                let formula = Formula::Operation {
+                    t,
+                    operand_1: operand(cmd.operand_1),
+                    operand_2: cmd.operand_2.map(operand),
+                };
+                Some((CellRef::from(cell), formula))
+            }
+            Command::Sleep(cmd) => {
+                let Value::Cell(cell) = cmd.target_cell else {
+                    return None;
+                };
+                let formula = Formula::Operation {
+                    t: Type::Slp,
+                    operand_1: operand(cmd.value),
+                    operand_2: None,
+                };
+                Some((CellRef::from(cell), formula))
+            }
+            _ => None,
+        }
+    }
+
+    /// Cells read by the formula. For ranges only the corners are returned.
+    pub fn references(&self) -> Vec<CellRef> {
+        match self {
+            Formula::Operation {
+                operand_1,
+                operand_2,
+                ..
+            } => [Some(*operand_1), *operand_2]
+                .into_iter()
+                .filter_map(|operand| match operand {
+                    Some(Operand::Cell(cell)) => Some(cell),
+                    _ => None,
+                })
+                .collect(),
+            Formula::Range { range, .. } => vec![range.start, range.end],
+        }
+    }
+}
+
+impl FromStr for Formula {
+    type Err = SheetError;
+
+    /// Parses the right hand side of a cell command, with or without a leading `=`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SheetError::InvalidFormula(s.to_string());
+        let trimmed = s.trim();
+        let body = trimmed.strip_prefix('=').unwrap_or(trimmed);
+        // The parser works on whole commands, so parse against a placeholder target
+        // and without bounds, which are checked by the spreadsheet instead
+        let command =
+            parse_cmd(&format!("A1={}", body), usize::MAX, usize::MAX).map_err(|_| invalid())?;
+        let (_, formula) = Formula::from_command(command).ok_or_else(invalid)?;
+        if let Formula::Operation {
+            t: Type::Slp,
+            operand_1: Operand::Constant(time),
+            ..
+        } = formula
+            && time < 0
+        {
+            return Err(invalid());
+        }
+        Ok(formula)
+    }
+}
+
+/// Writes the formula in the syntax accepted by `from_str`, without a leading `=`
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Formula::Operation {
+                t: Type::Slp,
+                operand_1,
+                ..
+            } => write!(f, "SLEEP({})", operand_1),
+            Formula::Operation {
+                operand_1,
+                operand_2: None,
+                ..
+            } => write!(f, "{}", operand_1),
+            Formula::Operation {
+                t,
+                operand_1,
+                operand_2: Some(operand_2),
+            } => write!(f, "{}{}{}", operand_1, t, operand_2),
+            Formula::Range { t, range } => write!(f, "{}({})", t, range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(s: &str) -> CellRef {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_operation() {
+        assert_eq!(
+            "=A1*3".parse(),
+            Ok(Formula::Operation {
+                t: Type::Mul,
+                operand_1: Operand::Cell(cell("A1")),
+                operand_2: Some(Operand::Constant(3)),
+            })
+        );
+        assert_eq!(
+            " 42 ".parse(),
+            Ok(Formula::Operation {
+                t: Type::Add,
+                operand_1: Operand::Constant(42),
+                operand_2: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_range_and_sleep() {
+        assert_eq!(
+            "SUM(A1:B2)".parse(),
+            Ok(Formula::Range {
+                t: Type::Sum,
+                range: "A1:B2".parse().unwrap(),
+            })
+        );
+        assert_eq!(
+            "SLEEP(B1)".parse::<Formula>().unwrap().references(),
+            vec![cell("B1")]
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for s in [
+            "A1*3",
+            "42",
+            "-7",
+            "B2+-3",
+            "SUM(A1:B2)",
+            "STDEV(C1:C9)",
+            "SLEEP(B1)",
+        ] {
+            let formula: Formula = s.parse().unwrap();
+            assert_eq!(formula.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for s in [
+            "",
+            "A1+",
+            "SUM(B2:A1)",
+            "FOO(A1:A2)",
+            "SLEEP(-1)",
+            "q",
+            "A0",
+        ] {
+            assert_eq!(
+                s.parse::<Formula>(),
+                Err(SheetError::InvalidFormula(s.to_string()))
+            );
+        }
+    }
+}
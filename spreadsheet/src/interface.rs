@@ -1,10 +1,18 @@
+use crate::error::SheetError;
+use crate::formula::Formula;
 use crate::parser;
-use crate::parser::cell::Value;
-use crate::parser::command::Command;
+use crate::parser::command::{Command, VCSCommand};
 use crate::parser::error::Error;
 use crate::parser::print_output::print_sheet;
 use crate::spreadsheet::SpreadSheet;
-use crate::utils::{Status, Type};
+use crate::utils::Status;
+use crate::vcs::backend::{self, VcsBackend};
+use crate::vcs::bisect::bisect;
+use crate::vcs::blame::blame;
+use crate::vcs::bundle;
+use crate::vcs::commit::CommitOptions;
+use crate::vcs::error::VcsError;
+use crate::vcs::log::{LogOptions, log};
 use crate::vcs::vcs_engine::VersionControl;
 use std::io::{self, Write};
 use std::time::Instant;
@@ -12,7 +20,7 @@ use std::time::Instant;
 pub fn process_command(
     user_input: &str,
     spreadsheet: &mut SpreadSheet,
-    vcs: &mut VersionControl,
+    vcs: &mut dyn VcsBackend,
     row: &mut usize,
     col: &mut usize,
     enable_output: &mut bool,
@@ -23,7 +31,11 @@ pub fn process_command(
     let start = Instant::now();
     let user_command: Result<Command, Error> =
         parser::command_parser::parse_cmd(user_input, max_rows, max_cols);
-    let Ok(command) = user_command else {
+    let Ok(command) = user_command.inspect_err(|err| {
+        if let Error::Usage(usage) = err {
+            println!("{}", usage);
+        }
+    }) else {
         let duration = start.elapsed();
         if *enable_output {
             print_sheet(1, 1, spreadsheet, max_rows, max_cols);
@@ -32,69 +44,13 @@ pub fn process_command(
         io::stdout().flush().unwrap();
         return;
     };
-    let status;
+    let mut status;
     match command {
-        Command::Range(cmd) => {
-            let Value::Cell(cell) = cmd.target_cell else {
-                panic!();
-            };
-            let Value::Cell(operand_1) = cmd.operand_1 else {
-                panic!();
-            };
-            let Value::Cell(operand_2) = cmd.operand_2 else {
-                panic!();
-            };
-            let t = Type::from_str(cmd.function.as_str());
-            status = match spreadsheet.set_cell_equation(
-                (cell.row - 1, cell.col - 1),
-                Some((operand_1.row - 1, operand_1.col - 1)),
-                Some((operand_2.row - 1, operand_2.col - 1)),
-                None,
-                None,
-                t,
-            ) {
-                Status::Ok => true,
-                Status::Err => false,
-            };
-
-            if *enable_output {
-                print_sheet(1, 1, spreadsheet, max_rows, max_cols);
-            }
-        }
-        Command::Arithmetic(cmd) => {
-            let Value::Cell(cell) = cmd.target_cell else {
-                panic!();
-            };
-
-            let (cell_1, const_1) = match cmd.operand_1 {
-                Value::Cell(cell) => (Some((cell.row - 1, cell.col - 1)), None),
-                Value::Constant(constant) => (None, Some(constant)),
-            };
-
-            let (cell_2, const_2) = match cmd.operand_2 {
-                Some(operand_2) => match operand_2 {
-                    Value::Cell(cell_2) => (Some((cell_2.row - 1, cell_2.col - 1)), None),
-                    Value::Constant(constant) => (None, Some(constant)),
-                },
-                None => (None, None),
-            };
-
-            let t = match cmd.operator {
-                Some(op) => Type::from_str(op.as_str()),
-                None => Type::from_str("+"),
-            };
-
-            status = match spreadsheet.set_cell_equation(
-                (cell.row - 1, cell.col - 1),
-                cell_1,
-                cell_2,
-                const_1,
-                const_2,
-                t,
-            ) {
-                Status::Ok => true,
-                Status::Err => false,
-            };
+        Command::Range(_) | Command::Arithmetic(_) | Command::Sleep(_) => {
+            status = matches!(
+                apply_cell_command(command, spreadsheet),
+                Some(Ok(Status::Ok))
+            );
 
             if *enable_output {
                 print_sheet(1, 1, spreadsheet, max_rows, max_cols);
@@ -159,59 +115,370 @@ pub fn process_command(
             }
             status = true;
         }
-        Command::Sleep(cmd) => {
-            let Value::Cell(target_cell) = cmd.target_cell else {
-                panic!();
-            };
-
-            let (cell_1, const_1) = match cmd.value {
-                Value::Cell(cell) => (Some((cell.row - 1, cell.col - 1)), None),
-                Value::Constant(constant) => (None, Some(constant)),
-            };
-
-            let t = Type::Slp;
-
-            status = match spreadsheet.set_cell_equation(
-                (target_cell.row - 1, target_cell.col - 1),
-                cell_1,
-                None,
-                const_1,
-                None,
-                t,
-            ) {
-                Status::Ok => true,
-                Status::Err => false,
-            };
-
-            if *enable_output {
-                print_sheet(1, 1, spreadsheet, max_rows, max_cols);
-            }
-        }
         Command::Vcs(cmd) => {
             status = true;
             let command = cmd.command.clone();
             let command = command.as_str();
-            match command {
-                "list" => {
-                    vcs.list();
+            if command == "migrate" || vcs.full().is_none() {
+                if let Err(err) = process_backend_command(&cmd, spreadsheet, vcs) {
+                    println!("{}", err);
+                    status = false;
                 }
-                "commit" => {
-                    if let Some(argument) = cmd.argument {
-                        vcs.commit(&argument, spreadsheet);
-                    }
+                if *enable_output && matches!(command, "checkout" | "reset") {
+                    print_sheet(1, 1, spreadsheet, max_rows, max_cols);
                 }
-                "checkout" => {
-                    if let Some(argument) = cmd.argument {
-                        if let Ok(commit_id) = argument[0..].parse::<usize>() {
-                            let new_spreadsheet = vcs.checkout(commit_id);
-                            *spreadsheet = new_spreadsheet;
+            } else if let Some(vcs) = vcs.full() {
+                // Commands that replace the working sheet refuse to drop uncommitted changes
+                let clean = |vcs: &VersionControl, spreadsheet: &mut SpreadSheet| {
+                    if cmd.has_flag("--force") {
+                        Ok(())
+                    } else {
+                        vcs.check_clean(spreadsheet)
+                    }
+                };
+                match command {
+                    "list" => {
+                        vcs.list();
+                    }
+                    "commit" | "amend" => {
+                        let options = if command == "amend" {
+                            CommitOptions::parse_amend(&cmd.arguments)
+                        } else {
+                            CommitOptions::parse(&cmd.arguments)
+                        };
+                        match options.and_then(|options| vcs.commit_with(&options, spreadsheet)) {
+                            Ok(lines) => {
+                                for line in lines {
+                                    println!("{}", line);
+                                }
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                status = false;
+                            }
+                        }
+                    }
+                    "bisect" => {
+                        // Every subcommand but `start` may check out another commit
+                        let moves = cmd.argument() != Some("start");
+                        let result = match clean(vcs, spreadsheet) {
+                            Err(err) if moves => Err(err),
+                            _ => {
+                                let result = bisect(vcs, &cmd.arguments);
+                                if moves {
+                                    *spreadsheet = vcs.head_spreadsheet();
+                                }
+                                result
+                            }
+                        };
+                        match result {
+                            Ok(lines) => {
+                                for line in lines {
+                                    println!("{}", line);
+                                }
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                status = false;
+                            }
+                        }
+                        if *enable_output && moves {
+                            print_sheet(1, 1, spreadsheet, max_rows, max_cols);
+                        }
+                    }
+                    "squash" => {
+                        let result = vcs.resolve_commit(&cmd.arguments[0]).and_then(|from| {
+                            let to = vcs.resolve_commit(&cmd.arguments[1])?;
+                            vcs.squash(from, to)?;
+                            println!("Squashed commits {} to {} into commit {}", from, to, to);
+                            Ok(())
+                        });
+                        if let Err(err) = result {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    }
+                    "checkout" => {
+                        if let Some(argument) = cmd.argument() {
+                            match clean(vcs, spreadsheet)
+                                .and_then(|_| vcs.resolve_commit(argument))
+                                .and_then(|commit_id| vcs.checkout(commit_id))
+                            {
+                                Ok(new_spreadsheet) => {
+                                    *spreadsheet = new_spreadsheet;
+                                    print_value_mismatches(vcs, spreadsheet);
+                                }
+                                Err(err) => {
+                                    println!("{}", err);
+                                    status = false;
+                                }
+                            }
+                            if *enable_output {
+                                print_sheet(1, 1, spreadsheet, max_rows, max_cols);
+                            }
+                        }
+                    }
+                    "stash" => {
+                        let result = match cmd.argument() {
+                            Some("list") => {
+                                for line in vcs.list_stashes() {
+                                    println!("{}", line);
+                                }
+                                Ok(None)
+                            }
+                            Some(_) => vcs.stash_pop(spreadsheet).map(Some),
+                            None => vcs.stash(spreadsheet).map(Some),
+                        };
+                        match result {
+                            Ok(Some(new_spreadsheet)) => *spreadsheet = new_spreadsheet,
+                            Ok(None) => (),
+                            Err(err) => {
+                                println!("{}", err);
+                                status = false;
+                            }
                         }
                         if *enable_output {
                             print_sheet(1, 1, spreadsheet, max_rows, max_cols);
                         }
                     }
+                    "tag" => {
+                        let arguments: Vec<&str> =
+                            cmd.arguments.iter().map(String::as_str).collect();
+                        let result = match arguments[..] {
+                            [] => {
+                                for line in vcs.list_tags() {
+                                    println!("{}", line);
+                                }
+                                Ok(())
+                            }
+                            ["-d", name] => vcs.delete_tag(name),
+                            [name] => vcs.create_tag(name, vcs.head()),
+                            [name, revision] => vcs
+                                .resolve_commit(revision)
+                                .and_then(|commit_id| vcs.create_tag(name, commit_id)),
+                            _ => Ok(()),
+                        };
+                        if let Err(err) = result {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    }
+                    "remote" => {
+                        let arguments: Vec<&str> =
+                            cmd.arguments.iter().map(String::as_str).collect();
+                        let result = match arguments[..] {
+                            ["add", name, path] => vcs.add_remote(name, path),
+                            ["remove", name] => vcs.remove_remote(name),
+                            _ => {
+                                for line in vcs.list_remotes() {
+                                    println!("{}", line);
+                                }
+                                Ok(())
+                            }
+                        };
+                        if let Err(err) = result {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    }
+                    "bundle" => {
+                        let arguments: Vec<&str> =
+                            cmd.arguments.iter().map(String::as_str).collect();
+                        let result = match arguments[..] {
+                        ["create", file] => bundle::create(vcs, file)
+                            .map(|commits| println!("Bundled {} commits into `{}`", commits, file)),
+                        ["import", file, dir] => bundle::import(file, dir).map(|imported| {
+                            println!(
+                                "Imported {} commits into `{}`, open it with --vcs --vcs_dir {}",
+                                imported.commits().len(),
+                                dir,
+                                dir
+                            )
+                        }),
+                        _ => Ok(()),
+                    };
+                        if let Err(err) = result {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    }
+                    "push" => match vcs.push(cmd.argument()) {
+                        Ok(line) => println!("{}", line),
+                        Err(err) => {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    },
+                    "pull" => {
+                        match clean(vcs, spreadsheet).and_then(|_| vcs.pull(cmd.argument())) {
+                            Ok((new_spreadsheet, lines)) => {
+                                *spreadsheet = new_spreadsheet;
+                                for line in lines {
+                                    println!("{}", line);
+                                }
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                status = false;
+                            }
+                        }
+                        if *enable_output {
+                            print_sheet(1, 1, spreadsheet, max_rows, max_cols);
+                        }
+                    }
+                    "branch" => match cmd.argument() {
+                        Some(name) => {
+                            if let Err(err) = vcs.create_branch(name) {
+                                println!("{}", err);
+                                status = false;
+                            }
+                        }
+                        None => {
+                            for line in vcs.list_branches() {
+                                println!("{}", line);
+                            }
+                        }
+                    },
+                    "merge" | "revert" | "reset" | "cherry-pick" => {
+                        if let Some(argument) = cmd.argument() {
+                            match clean(vcs, spreadsheet)
+                                .and_then(|_| vcs.resolve_commit(argument))
+                                .and_then(|commit_id| match command {
+                                    "merge" => vcs.merge(commit_id),
+                                    "revert" => vcs.revert(commit_id),
+                                    "reset" => vcs.reset(commit_id).map(|sheet| (sheet, vec![])),
+                                    _ => vcs.cherry_pick(commit_id),
+                                }) {
+                                Ok((new_spreadsheet, lines)) => {
+                                    *spreadsheet = new_spreadsheet;
+                                    for line in lines {
+                                        println!("{}", line);
+                                    }
+                                }
+                                Err(err) => {
+                                    println!("{}", err);
+                                    status = false;
+                                }
+                            }
+                            if *enable_output {
+                                print_sheet(1, 1, spreadsheet, max_rows, max_cols);
+                            }
+                        }
+                    }
+                    "diff" => {
+                        let resolve = |argument: Option<&String>| {
+                            argument
+                                .map(|argument| vcs.resolve_commit(argument))
+                                .transpose()
+                        };
+                        match resolve(cmd.arguments.first()).and_then(|from| {
+                            let to = resolve(cmd.arguments.get(1))?;
+                            vcs.diff(from, to, spreadsheet)
+                        }) {
+                            Ok(changes) => {
+                                for change in changes {
+                                    println!("{}", change);
+                                }
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                status = false;
+                            }
+                        }
+                    }
+                    "status" => {
+                        for line in vcs.status(spreadsheet) {
+                            println!("{}", line);
+                        }
+                    }
+                    "gc" => match vcs.gc() {
+                        Ok((written, removed)) => {
+                            println!(
+                                "Wrote {} snapshots, removed {} unused files",
+                                written, removed
+                            );
+                        }
+                        Err(err) => {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    },
+                    "reload" => match vcs.reload() {
+                        Ok(()) => println!("Reloaded, HEAD is at commit {}", vcs.head()),
+                        Err(err) => {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    },
+                    "fsck" => {
+                        let problems = vcs.fsck();
+                        if problems.is_empty() {
+                            println!("No problems found");
+                        }
+                        for problem in &problems {
+                            println!("{}", problem);
+                        }
+                        status = problems.is_empty();
+                    }
+                    "blame" => {
+                        if let Some(range) = cmd.argument() {
+                            match blame(vcs, range, spreadsheet) {
+                                Ok(lines) => {
+                                    for line in lines {
+                                        println!("{}", line);
+                                    }
+                                }
+                                Err(err) => {
+                                    println!("{}", err);
+                                    status = false;
+                                }
+                            }
+                        }
+                    }
+                    "log" => match LogOptions::parse(&cmd.arguments) {
+                        Ok(options) => {
+                            for line in log(vcs, &options) {
+                                println!("{}", line);
+                            }
+                        }
+                        Err(err) => {
+                            println!("{}", err);
+                            status = false;
+                        }
+                    },
+                    "ours" | "theirs" => {
+                        let take_theirs = command == "theirs";
+                        match vcs.resolve(cmd.argument(), take_theirs, spreadsheet) {
+                            Ok(lines) => {
+                                for line in lines {
+                                    println!("{}", line);
+                                }
+                            }
+                            Err(err) => {
+                                println!("{}", err);
+                                status = false;
+                            }
+                        }
+                        if *enable_output {
+                            print_sheet(1, 1, spreadsheet, max_rows, max_cols);
+                        }
+                    }
+                    "switch" => {
+                        if let Some(name) = cmd.argument() {
+                            match clean(vcs, spreadsheet).and_then(|_| vcs.switch(name)) {
+                                Ok(new_spreadsheet) => *spreadsheet = new_spreadsheet,
+                                Err(err) => {
+                                    println!("{}", err);
+                                    status = false;
+                                }
+                            }
+                            if *enable_output {
+                                print_sheet(1, 1, spreadsheet, max_rows, max_cols);
+                            }
+                        }
+                    }
+                    _ => (),
                 }
-                _ => (),
             }
         }
     }
@@ -223,3 +490,84 @@ pub fn process_command(
     }
     io::stdout().flush().unwrap();
 }
+
+/// Warns about cells of the checked out sheet that compute differently than when HEAD was
+/// committed
+fn print_value_mismatches(vcs: &VersionControl, spreadsheet: &SpreadSheet) {
+    match vcs.verify_values(vcs.head(), spreadsheet) {
+        Ok(mismatches) if !mismatches.is_empty() => {
+            println!(
+                "Warning: {} cells compute differently than when commit {} was made",
+                mismatches.len(),
+                vcs.head()
+            );
+            for mismatch in mismatches {
+                println!("  {}", mismatch);
+            }
+        }
+        Ok(_) => (),
+        Err(err) => println!("{}", err),
+    }
+}
+
+/// Runs `gitsap migrate`, and the gitsap commands every backend supports on backends other
+/// than the diff engine
+fn process_backend_command(
+    cmd: &VCSCommand,
+    spreadsheet: &mut SpreadSheet,
+    vcs: &mut dyn VcsBackend,
+) -> Result<(), VcsError> {
+    let (m, n) = vcs.get_m_n();
+    let clean = |vcs: &dyn VcsBackend, spreadsheet: &mut SpreadSheet| {
+        if cmd.has_flag("--force") {
+            Ok(())
+        } else {
+            vcs.check_clean(spreadsheet)
+        }
+    };
+    match (cmd.command.as_str(), cmd.argument()) {
+        ("migrate", Some(kind)) => {
+            let dir = &cmd.arguments[1];
+            let mut target = backend::create(kind, dir.clone(), m, n, false)?;
+            let commits = backend::migrate(vcs, target.as_mut())?;
+            println!(
+                "Migrated {} commits to a {} repository in `{}`, open it with --vcs --backend {} --vcs_dir {}",
+                commits, kind, dir, kind, dir
+            );
+        }
+        ("list", _) => vcs.list(),
+        ("commit", _) => {
+            let options = CommitOptions::parse(&cmd.arguments)?;
+            if options.amend {
+                return Err(VcsError::InvalidOption("--amend".to_string()));
+            }
+            let message = options.message.unwrap_or_default();
+            for line in vcs.commit(&message, options.author.as_deref(), spreadsheet)? {
+                println!("{}", line);
+            }
+        }
+        ("checkout" | "reset", Some(argument)) => {
+            let id = argument
+                .parse()
+                .map_err(|_| VcsError::UnknownRevision(argument.to_string()))?;
+            clean(vcs, spreadsheet)?;
+            *spreadsheet = if cmd.command == "checkout" {
+                vcs.checkout(id)?
+            } else {
+                vcs.reset(id)?
+            };
+        }
+        (command, _) => return Err(VcsError::NeedsDiffBackend(command.to_string())),
+    }
+    Ok(())
+}
+
+/// Applies a cell-updating command (range, arithmetic or sleep) to the spreadsheet.
+/// Returns `None` if the command does not update a cell.
+pub fn apply_cell_command(
+    command: Command,
+    spreadsheet: &mut SpreadSheet,
+) -> Option<Result<Status, SheetError>> {
+    let (cell, formula) = Formula::from_command(command)?;
+    Some(spreadsheet.set_cell_formula(cell, &formula))
+}
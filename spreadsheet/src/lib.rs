@@ -0,0 +1,38 @@
+//! A terminal spreadsheet engine with a mini version control system (`gitsap`).
+//!
+//! The main entry point is [`SpreadSheet`], which stores cell formulas and keeps
+//! dependent cells up to date. Cells are addressed with [`CellRef`] and [`RangeRef`],
+//! which parse and display A1 notation. Commands typed by users are parsed with
+//! [`parser::command_parser::parse_cmd`], and sheets can be versioned with
+//! [`vcs::vcs_engine::VersionControl`].
+//!
+//! ```
+//! use spreadsheet::{CellRef, Operand, SpreadSheet, Status, Type};
+//!
+//! let mut sheet = SpreadSheet::new(3, 3);
+//! let a1: CellRef = "A1".parse().unwrap();
+//! let b1: CellRef = "B1".parse().unwrap();
+//! // A1 = 2, B1 = A1 * 3
+//! sheet.set_cell_operation(a1, Type::Add, Operand::Constant(2), None).unwrap();
+//! let status = sheet.set_cell_operation(b1, Type::Mul, Operand::Cell(a1), Some(Operand::Constant(3)));
+//! assert_eq!(status, Ok(Status::Ok));
+//! assert_eq!(sheet.get_value(b1), Ok(Some(6)));
+//! ```
+
+pub mod cell_ref;
+mod equation;
+pub mod error;
+pub mod formula;
+pub mod interface;
+pub mod parser;
+pub mod server;
+pub mod spreadsheet;
+pub mod utils;
+mod value;
+pub mod vcs;
+
+pub use cell_ref::{CellRef, RangeRef};
+pub use error::SheetError;
+pub use formula::Formula;
+pub use spreadsheet::{CellEquationParameters, Operand, SpreadSheet};
+pub use utils::{Status, Type};
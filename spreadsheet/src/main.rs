@@ -1,12 +1,6 @@
-mod equation;
-mod interface;
-mod parser;
-mod spreadsheet;
-mod utils;
-mod value;
-mod vcs;
-
 use spreadsheet::SpreadSheet;
+use spreadsheet::vcs::backend::VcsBackend;
+use spreadsheet::{interface, parser, server, vcs};
 use std::env;
 use std::io;
 use std::io::Write;
@@ -31,12 +25,51 @@ pub fn main() {
             std::process::exit(1);
         }
 
-        let vcs = vcs::vcs_engine::VersionControl::dummy();
+        let vcs: Box<dyn VcsBackend> = Box::new(vcs::vcs_engine::VersionControl::dummy());
         (m, n, vcs)
+    } else if args.len() >= 2 && args[1] == "--serve" {
+        // Server mode: cargo run -- --serve 10 10 [--port 8080]
+        let m = args.get(2).and_then(|s| s.parse::<usize>().ok());
+        let n = args.get(3).and_then(|s| s.parse::<usize>().ok());
+        let (Some(m), Some(n)) = (m, n) else {
+            println!("Usage: cargo run -- --serve m n [--port PORT]");
+            std::process::exit(1);
+        };
+        if m == 0 || m > 999 || n == 0 || n > 18278 {
+            println!("Invalid m or n value. m should be between 1 and 999, n between 1 and 18278.");
+            std::process::exit(1);
+        }
+
+        let port = match args.get(4).map(|s| s.as_str()) {
+            None => 8080,
+            Some("--port") => args
+                .get(5)
+                .and_then(|s| s.parse::<u16>().ok())
+                .unwrap_or_else(|| {
+                    println!("Invalid or missing value for --port");
+                    std::process::exit(1);
+                }),
+            Some(arg) => {
+                println!("Unknown argument: {}", arg);
+                std::process::exit(1);
+            }
+        };
+
+        let server = server::Server::bind(("127.0.0.1", port), m, n).unwrap_or_else(|e| {
+            println!("Failed to bind to port {}: {}", port, e);
+            std::process::exit(1);
+        });
+        if let Ok(addr) = server.local_addr() {
+            println!("Serving a {}x{} spreadsheet on http://{}", m, n, addr);
+        }
+        server.run();
+        return;
     } else if args.len() >= 2 && args[1] == "--vcs" {
         let mut vcs_dir = None;
         let mut rows = None;
         let mut cols = None;
+        let mut force = false;
+        let mut backend = "diff".to_string();
 
         let mut i = 2;
         while i < args.len() {
@@ -70,6 +103,14 @@ pub fn main() {
                             }),
                     );
                 }
+                "--force" => force = true,
+                "--backend" => {
+                    i += 1;
+                    backend = args.get(i).cloned().unwrap_or_else(|| {
+                        println!("Missing value for --backend");
+                        std::process::exit(1);
+                    });
+                }
                 _ => {
                     println!("Unknown argument: {}", args[i]);
                     std::process::exit(1);
@@ -79,8 +120,10 @@ pub fn main() {
         }
 
         if let Some(dir) = vcs_dir {
-            let serial_vcs = vcs::vcs_engine::SerialVcs::load(&dir);
-            let vcs = vcs::vcs_engine::VersionControl::load(serial_vcs, dir.clone());
+            let vcs = vcs::backend::open(&backend, dir).unwrap_or_else(|err| {
+                println!("{}", err);
+                std::process::exit(1);
+            });
             let (m, n) = vcs.get_m_n();
             (m, n, vcs)
         } else if let (Some(m), Some(n)) = (rows, cols) {
@@ -91,7 +134,10 @@ pub fn main() {
                 std::process::exit(1);
             }
             let vcs_dir = "./vcs_dir".to_string();
-            let vcs = vcs::vcs_engine::VersionControl::new(vcs_dir, &m, &n);
+            let vcs = vcs::backend::create(&backend, vcs_dir, m, n, force).unwrap_or_else(|err| {
+                println!("{}", err);
+                std::process::exit(1);
+            });
             (m, n, vcs)
         } else {
             println!("Provide either --vcs_dir or both --rows and --cols");
@@ -99,7 +145,7 @@ pub fn main() {
         }
     } else {
         println!(
-            "Invalid arguments. Use either `cargo run -- m n` or `cargo run -- --vcs [--vcs_dir path | --rows m --cols n]`."
+            "Invalid arguments. Use either `cargo run -- m n`, `cargo run -- --vcs [--backend diff|snapshot] [--vcs_dir path | --rows m --cols n [--force]]` or `cargo run -- --serve m n [--port PORT]`."
         );
         std::process::exit(1);
     };
@@ -107,7 +153,20 @@ pub fn main() {
     let mut vcs = vcs;
     let mut spreadsheet = SpreadSheet::new(m, n);
     if vcs.get_m_n() != (0, 0) {
-        vcs.commit("Initial_commit", &mut spreadsheet);
+        if vcs.head() == 0 {
+            // Brand-new repository
+            if let Err(err) = vcs.commit("Initial_commit", None, &mut spreadsheet) {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        } else {
+            // A merge stopped on conflicts picks up where it left off
+            let merged = vcs.full().and_then(|vcs| vcs.merge_spreadsheet());
+            if merged.is_some() {
+                println!("A merge is in progress, see `gitsap status`");
+            }
+            spreadsheet = merged.unwrap_or_else(|| vcs.head_spreadsheet());
+        }
     }
 
     parser::print_output::print_sheet(1, 1, &spreadsheet, m, n);
@@ -126,7 +185,7 @@ pub fn main() {
         interface::process_command(
             user_input,
             &mut spreadsheet,
-            &mut vcs,
+            vcs.as_mut(),
             &mut row,
             &mut col,
             &mut enable_output,
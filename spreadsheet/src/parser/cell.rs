@@ -26,13 +26,16 @@ pub enum Value {
 
 /// Converts a cell's string representation to Cell struct. Example - A1 -> Cell { row: 1, col: 1 }
 pub fn convert_string_to_cell(cell: &str) -> Option<Cell> {
-    let mut col = 0;
+    let mut col: usize = 0;
     let mut row_start = 0;
 
     // Starts parsing the column
     for (i, c) in cell.chars().enumerate() {
         if c.is_ascii_alphabetic() {
-            col = col * 26 + ((c as u8 - b'A' + 1) as usize);
+            // Overly long column names would overflow, so they are rejected
+            col = col
+                .checked_mul(26)?
+                .checked_add((c as u8 - b'A' + 1) as usize)?;
         } else {
             row_start = i;
             break;
@@ -153,5 +156,6 @@ mod tests {
         assert_eq!(convert_string_to_cell(""), None); // Empty string
         assert_eq!(convert_string_to_cell("A-1"), None); // String to integer parsing failed
         assert_eq!(convert_string_to_cell("A1.2"), None); // String to integer parsing failed
+        assert_eq!(convert_string_to_cell("AAAAAAAAAAAAAAAAAAAA1"), None); // Column overflows
     }
 }
@@ -1,4 +1,7 @@
 use crate::parser::cell::Value;
+use crate::parser::error::Error;
+use crate::vcs::commit::CommitOptions;
+use crate::vcs::log::LogOptions;
 
 use super::cell::Cell;
 
@@ -137,19 +140,160 @@ impl SleepCommand {
 #[derive(PartialEq, Debug, Clone)]
 pub struct VCSCommand {
     pub command: String,
-    pub argument: Option<String>,
+    pub arguments: Vec<String>,
 }
 
 impl VCSCommand {
-    pub fn is_valid_vcs_command(&self) -> bool {
-        let command = self.command.as_str();
-        !matches!(
-            (command, self.argument.clone()),
-            ("list", Some(_)) | ("commit", None) | ("checkout", None)
-        )
+    /// Builds `gitsap <command> <arguments>`, splitting the arguments at whitespace except
+    /// inside quotes, and checks them against the command's usage
+    pub fn parse(command: &str, arguments: &str) -> Result<Self, Error> {
+        let vcs_command = VCSCommand {
+            command: command.to_string(),
+            arguments: split_arguments(arguments)?,
+        };
+        vcs_command.validate()?;
+        Ok(vcs_command)
+    }
+
+    /// The first argument that is not a `--` option, if any
+    pub fn argument(&self) -> Option<&str> {
+        self.arguments
+            .iter()
+            .find(|argument| !argument.starts_with("--"))
+            .map(|argument| argument.as_str())
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.arguments.iter().any(|argument| argument == flag)
+    }
+
+    /// Usage of the command, `None` if there is no such command
+    fn usage(&self) -> Option<&'static str> {
+        let usage = match self.command.as_str() {
+            "list" => "gitsap list",
+            "status" => "gitsap status",
+            "gc" => "gitsap gc",
+            "fsck" => "gitsap fsck",
+            "reload" => "gitsap reload",
+            "commit" => "gitsap commit [-m] <MSG> [--amend] [--author <NAME>] [--no-verify]",
+            "amend" => "gitsap amend [[-m] <MSG>] [--author <NAME>] [--no-verify]",
+            "squash" => "gitsap squash <FROM> <TO>",
+            "bisect" => {
+                "gitsap bisect start | good [<COMMIT>] | bad [<COMMIT>] | reset | run <CONDITION>"
+            }
+            "checkout" => "gitsap checkout <COMMIT_ID> [--force]",
+            "switch" => "gitsap switch <BRANCH> [--force]",
+            "merge" => "gitsap merge <COMMIT_ID>",
+            "revert" => "gitsap revert <COMMIT_ID>",
+            "reset" => "gitsap reset <COMMIT_ID> [--force]",
+            "cherry-pick" => "gitsap cherry-pick <COMMIT_ID>",
+            "branch" => "gitsap branch [<NAME>]",
+            "tag" => "gitsap tag [<NAME> [<COMMIT>] | -d <NAME>]",
+            "ours" => "gitsap ours [<CELL>]",
+            "theirs" => "gitsap theirs [<CELL>]",
+            "blame" => "gitsap blame <CELL|RANGE>",
+            "diff" => "gitsap diff [<COMMIT_A> [<COMMIT_B>]]",
+            "log" => "gitsap log [--graph] [-n <COUNT>]",
+            "stash" => "gitsap stash [pop | list]",
+            "remote" => "gitsap remote [add <NAME> <PATH> | remove <NAME>]",
+            "push" => "gitsap push [<REMOTE>]",
+            "pull" => "gitsap pull [<REMOTE>]",
+            "bundle" => "gitsap bundle create <FILE> | bundle import <FILE> <DIR>",
+            "migrate" => "gitsap migrate <diff|snapshot> <DIR>",
+            _ => return None,
+        };
+        Some(usage)
+    }
+
+    /// Checks the number of arguments and the options of the command
+    pub fn validate(&self) -> Result<(), Error> {
+        let Some(usage) = self.usage() else {
+            return Err(Error::Usage(format!(
+                "`gitsap {}` is not a gitsap command",
+                self.command
+            )));
+        };
+        let count = self.arguments.len();
+        let forcible = matches!(self.command.as_str(), "checkout" | "switch" | "reset");
+        let checked = match self.command.as_str() {
+            "log" => LogOptions::parse(&self.arguments).map(|_| true),
+            "commit" => CommitOptions::parse(&self.arguments).map(|_| true),
+            "amend" => CommitOptions::parse_amend(&self.arguments).map(|_| true),
+            // Other commands only take `--force`, and only if they replace the working sheet
+            _ if self.arguments.iter().any(|argument| {
+                argument.starts_with("--") && !(forcible && argument == "--force")
+            }) =>
+            {
+                Ok(false)
+            }
+            "checkout" | "switch" | "reset" => {
+                Ok(count - usize::from(self.has_flag("--force")) == 1)
+            }
+            "list" | "status" | "gc" | "fsck" | "reload" => Ok(count == 0),
+            "merge" | "revert" | "cherry-pick" | "blame" => Ok(count == 1),
+            "branch" | "ours" | "theirs" => Ok(count <= 1),
+            "diff" => Ok(count <= 2),
+            "squash" => Ok(count == 2),
+            "bisect" => Ok(match self.argument() {
+                Some("start" | "reset") => count == 1,
+                Some("good" | "bad") => count <= 2,
+                Some("run") => count >= 2,
+                _ => false,
+            }),
+            "tag" => Ok(match self.argument() {
+                Some("-d") => count == 2,
+                Some(name) => !name.starts_with('-') && count <= 2,
+                None => true,
+            }),
+            "stash" => Ok(matches!(self.argument(), None | Some("pop" | "list")) && count <= 1),
+            "remote" => Ok(match self.argument() {
+                Some("add") => count == 3,
+                Some("remove") => count == 2,
+                Some(_) => false,
+                None => true,
+            }),
+            "push" | "pull" => Ok(count <= 1),
+            "migrate" => Ok(count == 2 && matches!(self.argument(), Some("diff" | "snapshot"))),
+            "bundle" => Ok(match self.argument() {
+                Some("create") => count == 2,
+                Some("import") => count == 3,
+                _ => false,
+            }),
+            _ => Ok(false),
+        };
+        match checked {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Error::Usage(format!("usage: {}", usage))),
+            Err(err) => Err(Error::Usage(format!("{}\nusage: {}", err, usage))),
+        }
     }
 }
 
+/// Splits at whitespace. A word starting with a double or single quote runs to the matching
+/// quote, so `-m "Q3 totals, fixed"` is two arguments while `don't` stays one word.
+fn split_arguments(arguments: &str) -> Result<Vec<String>, Error> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in arguments.chars() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => word.get_or_insert_default().push(c),
+            None if c.is_whitespace() => words.extend(word.take()),
+            None if (c == '"' || c == '\'') && word.is_none() => {
+                quote = Some(c);
+                word = Some(String::new());
+            }
+            None => word.get_or_insert_default().push(c),
+        }
+    }
+    if let Some(open) = quote {
+        return Err(Error::Usage(format!("missing closing {}", open)));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Command {
     Range(RangeCommand),
@@ -359,4 +503,75 @@ mod tests {
         };
         assert!(!cmd.is_valid_ui_command(max_rows, max_cols));
     }
+
+    #[test]
+    fn test_vcs_command_arguments() {
+        let cmd =
+            VCSCommand::parse("commit", r#" -m "Q3 totals, fixed!" --author 'Ada L'"#).unwrap();
+        assert_eq!(
+            cmd.arguments,
+            ["-m", "Q3 totals, fixed!", "--author", "Ada L"]
+        );
+        let cmd = VCSCommand::parse("commit", " don't  round ''").unwrap();
+        assert_eq!(cmd.arguments, ["don't", "round", ""]);
+
+        let usage = |command: &str, arguments: &str| match VCSCommand::parse(command, arguments) {
+            Err(Error::Usage(usage)) => usage,
+            _ => panic!("`gitsap {}{}` should be invalid", command, arguments),
+        };
+        assert_eq!(
+            usage("checkout", ""),
+            "usage: gitsap checkout <COMMIT_ID> [--force]"
+        );
+        assert_eq!(usage("commit", " \"unclosed"), "missing closing \"");
+        assert_eq!(
+            usage("commit", " --amned"),
+            "invalid option `--amned`\nusage: gitsap commit [-m] <MSG> [--amend] [--author <NAME>] [--no-verify]"
+        );
+        assert_eq!(
+            usage("commit", ""),
+            "commit message is empty\nusage: gitsap commit [-m] <MSG> [--amend] [--author <NAME>] [--no-verify]"
+        );
+        assert_eq!(
+            usage("rebase", ""),
+            "`gitsap rebase` is not a gitsap command"
+        );
+        assert!(VCSCommand::parse("tag", " -d v1").is_ok());
+        let cmd = VCSCommand::parse("checkout", " --force main").unwrap();
+        assert!(cmd.has_flag("--force") && cmd.argument() == Some("main"));
+        assert_eq!(
+            usage("merge", " main --force"),
+            "usage: gitsap merge <COMMIT_ID>"
+        );
+        assert_eq!(usage("stash", " drop"), "usage: gitsap stash [pop | list]");
+        assert!(VCSCommand::parse("remote", " add origin \"/mnt/shared models\"").is_ok());
+        assert_eq!(
+            usage("remote", " add origin"),
+            "usage: gitsap remote [add <NAME> <PATH> | remove <NAME>]"
+        );
+        assert_eq!(
+            usage("push", " origin main"),
+            "usage: gitsap push [<REMOTE>]"
+        );
+        assert!(VCSCommand::parse("bundle", " import model.bundle ./copy").is_ok());
+        assert_eq!(
+            usage("migrate", " git ./copy"),
+            "usage: gitsap migrate <diff|snapshot> <DIR>"
+        );
+        assert_eq!(
+            usage("bundle", " create"),
+            "usage: gitsap bundle create <FILE> | bundle import <FILE> <DIR>"
+        );
+        assert!(VCSCommand::parse("amend", "").is_ok());
+        assert_eq!(usage("squash", " 3"), "usage: gitsap squash <FROM> <TO>");
+        assert!(VCSCommand::parse("bisect", " run \"A10 == 500\"").is_ok());
+        assert_eq!(
+            usage("bisect", " skip"),
+            "usage: gitsap bisect start | good [<COMMIT>] | bad [<COMMIT>] | reset | run <CONDITION>"
+        );
+        assert_eq!(
+            usage("tag", " -d"),
+            "usage: gitsap tag [<NAME> [<COMMIT>] | -d <NAME>]"
+        );
+    }
 }
@@ -18,9 +18,8 @@ pub fn parse_cmd(user_command: &str, max_rows: usize, max_cols: usize) -> Result
     let function: String = String::from(r"(MAX|MIN|AVG|STDEV|SUM)");
     let value: String = format!("({}|{})", cell, constant);
 
-    let vcs_command: String = String::from(
-        "^(gitsap\\s(?P<VCS_COMMAND>commit|list|checkout)(\\s(?P<VCS_INFO>[A-Za-z0-9]+))?\\s*)$",
-    );
+    let vcs_command: String =
+        String::from("^(gitsap\\s+(?P<VCS_COMMAND>[a-z\\-]+)(?P<VCS_ARGS>(\\s.*)?))$");
 
     let ui_command: String = format!(
         "^((?P<UI_COMMAND>w|d|a|s|q|(\\s*enable_output\\s*)|(\\s*disable_output\\s*))|(\\s*scroll_to (?P<SCROLL_TO_CELL>{})\\s*))$",
@@ -55,22 +54,8 @@ pub fn parse_cmd(user_command: &str, max_rows: usize, max_cols: usize) -> Result
 
     // Check for VCS Command
     if let Some(command) = captures.name("VCS_COMMAND") {
-        let vcs_command;
-        if let Some(info) = captures.name("VCS_INFO") {
-            vcs_command = VCSCommand {
-                command: command.as_str().to_string(),
-                argument: Some(info.as_str().to_string()),
-            };
-        } else {
-            vcs_command = VCSCommand {
-                command: command.as_str().to_string(),
-                argument: None,
-            };
-        }
-        if !vcs_command.is_valid_vcs_command() {
-            return Err(Error::InvalidInput);
-        }
-        return Ok(Command::Vcs(vcs_command));
+        let arguments = captures.name("VCS_ARGS").map_or("", |args| args.as_str());
+        return VCSCommand::parse(command.as_str(), arguments).map(Command::Vcs);
     }
 
     // First, check for UI command
@@ -286,6 +271,40 @@ mod tests {
         assert!(matches!(result, Ok(Command::Sleep(_))));
     }
 
+    #[test]
+    fn test_vcs_branch_commands() {
+        let result = parse_cmd("gitsap branch pessimistic_2", MAX_ROWS, MAX_COLS);
+        assert!(matches!(
+            result,
+            Ok(Command::Vcs(VCSCommand { ref command, ref arguments }))
+                if command == "branch" && arguments.len() == 1
+        ));
+        let result = parse_cmd("gitsap log --graph  -n 5", MAX_ROWS, MAX_COLS);
+        assert!(matches!(
+            result,
+            Ok(Command::Vcs(VCSCommand { ref arguments, .. })) if arguments == &["--graph", "-n", "5"]
+        ));
+        let result = parse_cmd("gitsap cherry-pick 3", MAX_ROWS, MAX_COLS);
+        assert!(matches!(
+            result,
+            Ok(Command::Vcs(VCSCommand { ref command, .. })) if command == "cherry-pick"
+        ));
+        let result = parse_cmd("gitsap blame A1:B5", MAX_ROWS, MAX_COLS);
+        assert!(matches!(
+            result,
+            Ok(Command::Vcs(VCSCommand { ref arguments, .. })) if arguments == &["A1:B5"]
+        ));
+        let result = parse_cmd("gitsap commit fixed Q3 totals, again!", MAX_ROWS, MAX_COLS);
+        assert!(matches!(
+            result,
+            Ok(Command::Vcs(VCSCommand { ref arguments, .. })) if arguments.len() == 4
+        ));
+        let result = parse_cmd("gitsap switch", MAX_ROWS, MAX_COLS);
+        assert!(matches!(result, Err(Error::Usage(_))));
+        let result = parse_cmd("gitsap status now", MAX_ROWS, MAX_COLS);
+        assert!(matches!(result, Err(Error::Usage(_))));
+    }
+
     // Test invalid sleep command (invalid cell in value)
     #[test]
     fn test_sleep_command_invalid_value() {
@@ -1,4 +1,7 @@
+#[derive(Debug)]
 pub enum Error {
     RegexError,
     InvalidInput,
+    /// A `gitsap` command with invalid arguments, with a message explaining its usage
+    Usage(String),
 }
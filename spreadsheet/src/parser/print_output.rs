@@ -1,7 +1,7 @@
 use crate::spreadsheet::SpreadSheet;
 
 /// Converts the column string to column number using 1-indexing
-fn column_number_to_letters(mut col: usize) -> String {
+pub fn column_number_to_letters(mut col: usize) -> String {
     let mut letters = String::new();
     while col > 0 {
         col -= 1;
@@ -29,10 +29,9 @@ pub fn print_sheet(
         print!("{}\t", row);
         for col in start_col..(start_col + 10).min(max_cols + 1) {
             let value = spreadsheet.get_cell_value(row - 1, col - 1);
-            if value.is_none() {
-                print!("ERR\t");
-            } else {
-                print!("{}\t", value.unwrap());
+            match value {
+                Ok(Some(value)) => print!("{}\t", value),
+                _ => print!("ERR\t"),
             }
         }
         println!();
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+/// Largest request body accepted. Bodies are formulas, so anything larger is refused
+/// before reading it.
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// Longest request line or header line accepted
+pub const MAX_LINE: usize = 8 * 1024;
+
+/// Most headers accepted in a request
+pub const MAX_HEADERS: usize = 100;
+
+/// A parsed HTTP/1.1 request. Header names are stored in lowercase
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|value| value.as_str())
+    }
+
+    /// Returns true if the client asked to upgrade the connection to a WebSocket
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.header("upgrade")
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+            && self.header("sec-websocket-key").is_some()
+    }
+}
+
+/// An HTTP response carrying a JSON body
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, body: String) -> Self {
+        Response { status, body }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        let body = serde_json::json!({ "error": message }).to_string();
+        Response { status, body }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.reason(),
+            self.body.len(),
+            self.body
+        )?;
+        writer.flush()
+    }
+}
+
+/// Why a request could not be read
+#[derive(Debug)]
+pub enum RequestError {
+    /// The connection failed or the request is not valid HTTP
+    Io(io::Error),
+    /// `Content-Length` is above [`MAX_BODY`]
+    TooLarge(usize),
+    /// A line is longer than [`MAX_LINE`] or there are more than [`MAX_HEADERS`] headers
+    HeadersTooLarge,
+}
+
+impl From<io::Error> for RequestError {
+    fn from(err: io::Error) -> Self {
+        RequestError::Io(err)
+    }
+}
+
+fn invalid_data(message: &str) -> RequestError {
+    RequestError::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+/// Reads a line of at most [`MAX_LINE`] bytes, without buffering more of a longer one
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, RequestError> {
+    let mut line = String::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_line(&mut line)?;
+    if line.len() > MAX_LINE {
+        return Err(RequestError::HeadersTooLarge);
+    }
+    Ok(line)
+}
+
+/// Reads a request line, headers and (if `Content-Length` is present) the body
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, RequestError> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid_data("Malformed request line"));
+    };
+    let method = method.to_string();
+    let path = path.to_string();
+
+    let mut headers = HashMap::new();
+    let mut count = 0;
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Err(invalid_data("Connection closed before end of headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        count += 1;
+        if count > MAX_HEADERS {
+            return Err(RequestError::HeadersTooLarge);
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(invalid_data("Malformed header"));
+        };
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length = match headers.get("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| invalid_data("Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(RequestError::TooLarge(length));
+    }
+    // Grows with the data received instead of trusting the announced length up front
+    let mut body = Vec::new();
+    reader.take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(invalid_data("Connection closed before end of body"));
+    }
+    let body = String::from_utf8(body).map_err(|_| invalid_data("Body is not valid UTF-8"))?;
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_request_with_body() {
+        let raw = "PUT /cells/A1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nB1+3";
+        let request = read_request(&mut raw.as_bytes()).ok().unwrap();
+        assert_eq!(request.method, "PUT");
+        assert_eq!(request.path, "/cells/A1");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.body, "B1+3");
+        assert!(!request.is_websocket_upgrade());
+    }
+
+    #[test]
+    fn test_read_request_websocket_upgrade() {
+        let raw = "GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nSec-WebSocket-Key: abc\r\n\r\n";
+        let request = read_request(&mut raw.as_bytes()).ok().unwrap();
+        assert!(request.is_websocket_upgrade());
+    }
+
+    #[test]
+    fn test_read_request_malformed() {
+        assert!(read_request(&mut "\r\n".as_bytes()).is_err());
+        assert!(read_request(&mut "GET / HTTP/1.1\r\nbroken\r\n\r\n".as_bytes()).is_err());
+        let truncated = "PUT /cells/A1 HTTP/1.1\r\nContent-Length: 10\r\n\r\nB1";
+        assert!(matches!(
+            read_request(&mut truncated.as_bytes()),
+            Err(RequestError::Io(_))
+        ));
+        let huge = "PUT /cells/A1 HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n";
+        assert!(matches!(
+            read_request(&mut huge.as_bytes()),
+            Err(RequestError::TooLarge(99999999999))
+        ));
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "A".repeat(MAX_LINE));
+        assert!(matches!(
+            read_request(&mut long.as_bytes()),
+            Err(RequestError::HeadersTooLarge)
+        ));
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X: 1\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert!(matches!(
+            read_request(&mut many.as_bytes()),
+            Err(RequestError::HeadersTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_response_write_to() {
+        let mut out = Vec::new();
+        Response::new(200, "{}".to_string())
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(out.contains("Content-Length: 2\r\n"));
+        assert!(out.ends_with("\r\n\r\n{}"));
+    }
+}
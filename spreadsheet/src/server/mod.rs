@@ -0,0 +1,373 @@
+pub mod http;
+
+use crate::cell_ref::{CellRef, RangeRef};
+use crate::error::SheetError;
+use crate::spreadsheet::SpreadSheet;
+use http::{MAX_BODY, Request, RequestError, Response, read_request};
+
+use std::io::{self, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Error, Message, WebSocket};
+
+/// Queues of the connected WebSocket clients, each drained by the client's own thread
+type Clients = Arc<Mutex<Vec<SyncSender<String>>>>;
+
+/// Updates queued for a WebSocket client before it is disconnected as too slow
+const CLIENT_QUEUE: usize = 64;
+
+/// How long a WebSocket client's thread waits for a frame before sending queued updates
+const CLIENT_POLL: Duration = Duration::from_millis(50);
+
+/// Value of a single cell as sent to clients. `value` is `null` for ERR cells
+#[derive(serde_derive::Serialize)]
+struct CellValue {
+    cell: String,
+    value: Option<i32>,
+}
+
+/// Work handed to the engine thread, which is the only owner of the spreadsheet
+enum Action {
+    Sheet,
+    Read(RangeRef),
+    Write(CellRef, String),
+}
+
+struct Job {
+    action: Action,
+    reply: Sender<Response>,
+}
+
+/// Serves a single spreadsheet over HTTP and pushes updates over WebSockets.
+///
+/// * `GET /sheet` returns the dimensions
+/// * `GET /cells/A1` or `GET /cells/A1:C3` returns cell values
+/// * `PUT /cells/A1` with a formula body (e.g. `B1+3`, `SUM(A1:A3)`) sets a cell
+/// * `GET /ws` upgrades to a WebSocket receiving `{"changed": [...]}` after every update
+pub struct Server {
+    listener: TcpListener,
+    m: usize,
+    n: usize,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, m: usize, n: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(Server { listener, m, n })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections forever. Each connection is handled on its own thread,
+    /// while all reads and writes of the spreadsheet go through one engine thread.
+    pub fn run(self) {
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+        let (jobs, receiver) = mpsc::channel();
+        let (m, n) = (self.m, self.n);
+        let engine_clients = Arc::clone(&clients);
+        thread::spawn(move || run_engine(m, n, receiver, engine_clients));
+
+        for stream in self.listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let jobs = jobs.clone();
+            let clients = Arc::clone(&clients);
+            thread::spawn(move || {
+                let _ = handle_connection(stream, m, n, jobs, clients);
+            });
+        }
+    }
+}
+
+fn run_engine(m: usize, n: usize, receiver: Receiver<Job>, clients: Clients) {
+    let mut spreadsheet = SpreadSheet::new(m, n);
+    for job in receiver {
+        let response = match job.action {
+            Action::Sheet => {
+                Response::new(200, serde_json::json!({ "rows": m, "cols": n }).to_string())
+            }
+            Action::Read(range) => match spreadsheet.get_range_values(range) {
+                Ok(values) => {
+                    let cells: Vec<CellValue> = values
+                        .into_iter()
+                        .map(|(cell, value)| CellValue {
+                            cell: cell.to_string(),
+                            value,
+                        })
+                        .collect();
+                    Response::new(200, serde_json::json!({ "cells": cells }).to_string())
+                }
+                Err(err) => Response::error(400, &err.to_string()),
+            },
+            Action::Write(cell, formula) => write_cell(&mut spreadsheet, cell, &formula, &clients),
+        };
+        let _ = job.reply.send(response);
+    }
+}
+
+fn write_cell(
+    spreadsheet: &mut SpreadSheet,
+    cell: CellRef,
+    formula: &str,
+    clients: &Clients,
+) -> Response {
+    let affected = match spreadsheet.get_affected_cells(cell.row, cell.col) {
+        Ok(affected) => affected,
+        Err(err) => return Response::error(400, &err.to_string()),
+    };
+    // Affected cells are all inside the sheet, so reading them cannot fail
+    let value_of = |spreadsheet: &SpreadSheet, (row, col): (usize, usize)| {
+        spreadsheet.get_cell_value(row, col).unwrap_or_default()
+    };
+    let before: Vec<Option<i32>> = affected
+        .iter()
+        .map(|&cell| value_of(spreadsheet, cell))
+        .collect();
+
+    match spreadsheet.set_formula(&cell.to_string(), formula) {
+        Ok(()) => (),
+        Err(err @ SheetError::Cycle(_)) => return Response::error(409, &err.to_string()),
+        Err(err) => return Response::error(400, &err.to_string()),
+    }
+
+    let changed: Vec<CellValue> = affected
+        .iter()
+        .zip(before)
+        .filter_map(|(&(row, col), old)| {
+            let value = value_of(spreadsheet, (row, col));
+            (value != old).then(|| CellValue {
+                cell: CellRef::new(row, col).to_string(),
+                value,
+            })
+        })
+        .collect();
+    let body = serde_json::json!({ "changed": changed }).to_string();
+
+    // Only queues the update, so a stalled client cannot hold up the engine thread
+    if !changed.is_empty() {
+        let mut clients = clients.lock().unwrap();
+        clients.retain(|client| client.try_send(body.clone()).is_ok());
+    }
+
+    Response::new(200, body)
+}
+
+/// Parses `A1` or `A1:C3`, rejecting ranges that fall outside an `m` x `n` sheet
+fn parse_range(range: &str, m: usize, n: usize) -> Option<RangeRef> {
+    let range = range.parse::<RangeRef>().ok()?;
+    (range.end.row < m && range.end.col < n).then_some(range)
+}
+
+fn route(request: &Request, m: usize, n: usize) -> Result<Action, Response> {
+    let path = request.path.trim_end_matches('/');
+    if path == "/sheet" {
+        return match request.method.as_str() {
+            "GET" => Ok(Action::Sheet),
+            _ => Err(Response::error(405, "Method not allowed")),
+        };
+    }
+    let Some(range) = path.strip_prefix("/cells/") else {
+        return Err(Response::error(404, "Not found"));
+    };
+    let Some(range) = parse_range(range, m, n) else {
+        return Err(Response::error(400, "Invalid cell or range"));
+    };
+    match request.method.as_str() {
+        "GET" => Ok(Action::Read(range)),
+        "PUT" | "POST" if range.len() == 1 => Ok(Action::Write(range.start, request.body.clone())),
+        "PUT" | "POST" => Err(Response::error(
+            400,
+            "Formulas can only be set on a single cell",
+        )),
+        _ => Err(Response::error(405, "Method not allowed")),
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    m: usize,
+    n: usize,
+    jobs: Sender<Job>,
+    clients: Clients,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    let request = match read_request(&mut reader) {
+        Ok(request) => request,
+        Err(RequestError::TooLarge(length)) => {
+            let message = format!("Body of {} bytes is larger than {} bytes", length, MAX_BODY);
+            return Response::error(413, &message).write_to(&mut stream);
+        }
+        Err(RequestError::HeadersTooLarge) => {
+            return Response::error(431, "Request line or headers too large").write_to(&mut stream);
+        }
+        Err(RequestError::Io(err)) => {
+            let message = format!("Malformed request: {}", err);
+            return Response::error(400, &message).write_to(&mut stream);
+        }
+    };
+
+    if request.path == "/ws" && request.is_websocket_upgrade() {
+        let key = request.header("sec-websocket-key").unwrap_or_default();
+        // Registered before the handshake so no update slips in between; updates sent
+        // meanwhile wait in the queue
+        let (sender, updates) = mpsc::sync_channel(CLIENT_QUEUE);
+        clients.lock().unwrap().push(sender);
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            derive_accept_key(key.as_bytes())
+        )?;
+        stream.flush()?;
+        stream.set_read_timeout(Some(CLIENT_POLL))?;
+        let socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        serve_websocket(socket, updates);
+        return Ok(());
+    }
+
+    let response = match route(&request, m, n) {
+        Ok(action) => {
+            let (reply, receiver) = mpsc::channel();
+            let _ = jobs.send(Job { action, reply });
+            receiver
+                .recv()
+                .unwrap_or_else(|_| Response::error(500, "Engine stopped"))
+        }
+        Err(response) => response,
+    };
+    response.write_to(&mut stream)
+}
+
+/// Sends queued updates to a WebSocket client and reads its frames in between, so pings
+/// are answered and a close is acknowledged. Returns when the client closes the connection,
+/// the connection fails, or the client falls too far behind and is dropped. Dropping
+/// `updates` then removes the client from the list on the next update.
+fn serve_websocket(mut socket: WebSocket<TcpStream>, updates: Receiver<String>) {
+    loop {
+        // Pongs are queued by `read` and written with the next frame or read
+        match socket.read() {
+            Ok(Message::Close(_)) => {
+                // Sends the queued reply to the close
+                let _ = socket.flush();
+                return;
+            }
+            Ok(_) => (),
+            Err(Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return,
+        }
+        loop {
+            match updates.try_recv() {
+                Ok(update) => {
+                    if socket.send(Message::text(update)).is_err() {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn start_server(m: usize, n: usize) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", m, n).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).unwrap();
+        let status = raw[9..12].parse().unwrap();
+        let (_, body) = raw.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let range = parse_range("B2:C3", 5, 5).unwrap();
+        assert_eq!(range.to_string(), "B2:C3");
+        assert!(parse_range("A1", 5, 5).is_some());
+        assert!(parse_range("C3:B2", 5, 5).is_none());
+        assert!(parse_range("F1", 5, 5).is_none());
+    }
+
+    #[test]
+    fn test_set_and_read_cells() {
+        let addr = start_server(5, 5);
+
+        let (status, body) = send(addr, "GET", "/sheet", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["rows"], 5);
+
+        let (status, _) = send(addr, "PUT", "/cells/A1", "2");
+        assert_eq!(status, 200);
+        let (status, body) = send(addr, "PUT", "/cells/A2", "=A1*3");
+        assert_eq!(status, 200);
+        assert_eq!(body["changed"][0]["value"], 6);
+
+        let (status, body) = send(addr, "PUT", "/cells/A1", "A2+1");
+        assert_eq!(status, 409);
+        assert!(body["error"].is_string());
+
+        let (status, _) = send(addr, "PUT", "/cells/A1", "A1+");
+        assert_eq!(status, 400);
+
+        let (status, body) = send(addr, "GET", "/cells/A1:A2", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["cells"][0]["cell"], "A1");
+        assert_eq!(body["cells"][1]["value"], 6);
+    }
+
+    #[test]
+    fn test_websocket_receives_changes() {
+        let addr = start_server(3, 3);
+        let (mut socket, _) = tungstenite::connect(format!("ws://{}/ws", addr)).unwrap();
+
+        send(addr, "PUT", "/cells/B1", "A1+1");
+        let message = socket.read().unwrap();
+        let body: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(body["changed"][0]["cell"], "B1");
+        assert_eq!(body["changed"][0]["value"], 1);
+
+        // Pings are answered, and a close is acknowledged
+        socket.send(Message::Ping(vec![7].into())).unwrap();
+        assert_eq!(socket.read().unwrap(), Message::Pong(vec![7].into()));
+        socket.close(None).unwrap();
+        loop {
+            match socket.read() {
+                Ok(Message::Close(_)) => continue,
+                Err(Error::ConnectionClosed) => break,
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+}
@@ -1,18 +1,27 @@
+use crate::cell_ref::{CellRef, RangeRef};
 use crate::equation::Equation;
+use crate::error::SheetError;
+use crate::formula::Formula;
 use crate::utils::{Coordinate, Status, Type};
 use crate::value::{SharedOperand, Value};
 
 use std::collections::HashMap;
+use std::fmt;
 
 // should expose set and get for cell value, and set for cell equation
 // all the traversal and updation methods should be defined here like findDownstream, toposort
+/// An `m` x `n` grid of cells. Every cell holds an equation, and setting an equation
+/// recomputes all the cells that depend on it. Coordinates are 0-indexed `(row, col)`.
 #[derive(Clone, serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct SpreadSheet {
+    /// Number of rows
     pub m: usize,
+    /// Number of columns
     pub n: usize,
-    pub cells: Vec<Vec<SharedOperand>>,
+    pub(crate) cells: Vec<Vec<SharedOperand>>,
 }
 
+/// The equation of a cell, as accepted by [`SpreadSheet::set_cell_equation`]
 #[derive(Debug, Clone)]
 pub struct CellEquationParameters {
     pub _coordinates: (usize, usize),
@@ -23,7 +32,34 @@ pub struct CellEquationParameters {
     pub equation_type: Type,
 }
 
+/// An operand of an arithmetic or sleep equation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Cell(CellRef),
+    Constant(i32),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Cell(cell) => write!(f, "{}", cell),
+            Operand::Constant(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Operand {
+    fn split(operand: Option<Operand>) -> (Option<(usize, usize)>, Option<i32>) {
+        match operand {
+            Some(Operand::Cell(cell)) => (Some(cell.into()), None),
+            Some(Operand::Constant(value)) => (None, Some(value)),
+            None => (None, None),
+        }
+    }
+}
+
 impl SpreadSheet {
+    /// Creates a spreadsheet with `m` rows and `n` columns, all set to 0
     pub fn new(m: usize, n: usize) -> Self {
         let mut cells = Vec::<Vec<SharedOperand>>::with_capacity(m);
 
@@ -37,33 +73,59 @@ impl SpreadSheet {
 
         SpreadSheet { m, n, cells }
     }
-    pub fn get_cell_value(&self, row: usize, col: usize) -> Option<i32> {
-        assert!(
-            col < self.n && row < self.m,
-            "get_cell_value: Invalid cell coordinates ({},{})",
-            row,
-            col
-        );
-        Value::get_value(&(self.cells[row][col].borrow()))
+    fn check_bounds(&self, row: usize, col: usize) -> Result<(), SheetError> {
+        if col < self.n && row < self.m {
+            Ok(())
+        } else {
+            Err(SheetError::OutOfBounds(CellRef::new(row, col)))
+        }
     }
 
-    pub fn get_cell_equation_parameters(&self, row: usize, col: usize) -> CellEquationParameters {
-        assert!(
-            col < self.n && row < self.m,
-            "get_cell_value: Invalid cell coordinates ({},{})",
-            row,
-            col
-        );
+    /// Returns the value of a cell, or `None` if the cell is in an error state
+    pub fn get_cell_value(&self, row: usize, col: usize) -> Result<Option<i32>, SheetError> {
+        self.check_bounds(row, col)?;
+        Ok(Value::get_value(&(self.cells[row][col].borrow())))
+    }
+
+    /// Returns true if the cell lies within the spreadsheet
+    pub fn contains(&self, cell: CellRef) -> bool {
+        cell.row < self.m && cell.col < self.n
+    }
+
+    /// Returns the value of a cell, or `None` if the cell is in an error state
+    pub fn get_value(&self, cell: CellRef) -> Result<Option<i32>, SheetError> {
+        self.get_cell_value(cell.row, cell.col)
+    }
+
+    /// Returns the value of every cell in the range, in row-major order
+    pub fn get_range_values(
+        &self,
+        range: RangeRef,
+    ) -> Result<Vec<(CellRef, Option<i32>)>, SheetError> {
+        self.check_bounds(range.end.row, range.end.col)?;
+        range
+            .cells()
+            .map(|cell| Ok((cell, self.get_value(cell)?)))
+            .collect()
+    }
+
+    /// Returns the equation currently stored in a cell
+    pub fn get_cell_equation_parameters(
+        &self,
+        row: usize,
+        col: usize,
+    ) -> Result<CellEquationParameters, SheetError> {
+        self.check_bounds(row, col)?;
 
         let cell_ref = self.cells[row][col].borrow();
 
         let coords = cell_ref.get_coordinate();
         let tcoords = (coords.0, coords.1);
-        let eq = cell_ref.get_equation();
+        let eq = cell_ref.get_equation()?;
 
         let ops = eq.get_operands();
 
-        let op1 = if ops.is_empty() {
+        let op1 = if !ops.is_empty() {
             Some(ops[0].borrow())
         } else {
             None
@@ -74,49 +136,37 @@ impl SpreadSheet {
             None
         };
 
+        // An operand is either a cell or a constant value, never both
         let (v1, c1) = match op1 {
-            Some(op) => (
-                op.get_value(),
-                if op.is_cell() {
-                    Some((op.get_coordinate().0, op.get_coordinate().1))
-                } else {
-                    None
-                },
-            ),
+            Some(op) if op.is_cell() => {
+                (None, Some((op.get_coordinate().0, op.get_coordinate().1)))
+            }
+            Some(op) => (op.get_value(), None),
             None => (None, None),
         };
 
         let (v2, c2) = match op2 {
-            Some(op) => (
-                op.get_value(),
-                if op.is_cell() {
-                    Some((op.get_coordinate().0, op.get_coordinate().1))
-                } else {
-                    None
-                },
-            ),
+            Some(op) if op.is_cell() => {
+                (None, Some((op.get_coordinate().0, op.get_coordinate().1)))
+            }
+            Some(op) => (op.get_value(), None),
             None => (None, None),
         };
 
         let t = eq.t;
-        CellEquationParameters {
+        Ok(CellEquationParameters {
             _coordinates: tcoords,
             operand1_coordinates: c1,
             operand2_coordinates: c2,
             operand1_value: v1,
             operand2_value: v2,
             equation_type: t,
-        }
+        })
     }
 
-    fn process_cell_equation(&self, row: usize, col: usize) -> Option<i32> {
-        assert!(
-            col < self.n && row < self.m,
-            "process_cell_equation: Invalid cell coordinates ({},{})",
-            row,
-            col
-        );
-        Value::get_equation(&(self.cells[row][col].borrow())).process_equation(self)
+    fn process_cell_equation(&self, row: usize, col: usize) -> Result<Option<i32>, SheetError> {
+        self.check_bounds(row, col)?;
+        Value::get_equation(&(self.cells[row][col].borrow()))?.process_equation(self)
     }
 
     fn get_indegrees(&self, row: usize, col: usize, set: &mut HashMap<(usize, usize), i32>) {
@@ -175,6 +225,18 @@ impl SpreadSheet {
         Some(order)
     }
 
+    /// Returns the cell itself and every cell that (transitively) depends on it
+    pub fn get_affected_cells(
+        &self,
+        row: usize,
+        col: usize,
+    ) -> Result<Vec<(usize, usize)>, SheetError> {
+        self.check_bounds(row, col)?;
+        let mut in_degrees = HashMap::new();
+        self.get_indegrees(row, col, &mut in_degrees);
+        Ok(in_degrees.into_keys().collect())
+    }
+
     fn do_operation(&mut self, row: usize, col: usize) -> bool {
         // print!("do_operation: ");
         // self.cells[row][col].borrow().print();
@@ -193,13 +255,23 @@ impl SpreadSheet {
 
         // for each cell in the order, process the equation and set the value
         for coord in order {
-            let val = self.process_cell_equation(coord.0, coord.1);
+            // Cells whose equation cannot be evaluated (e.g. sleeping for a negative
+            // time after an upstream change) become ERR cells
+            let val = self
+                .process_cell_equation(coord.0, coord.1)
+                .unwrap_or_default();
             self.cells[coord.0][coord.1].borrow_mut().set_value(val);
         }
 
         true
     }
 
+    /// Sets the equation `t` of the cell at `cor`. Each operand is either a cell
+    /// (`c1`/`c2`) or a constant (`v1`/`v2`). `SLEEP` takes only the first operand,
+    /// range functions take the top-left and bottom-right cells of the range.
+    ///
+    /// Returns `Status::Err` and leaves the sheet unchanged if the equation creates a cycle,
+    /// and an error if the cells are out of bounds or the operands do not fit `t`.
     pub fn set_cell_equation(
         &mut self,
         cor: (usize, usize),
@@ -208,23 +280,17 @@ impl SpreadSheet {
         v1: Option<i32>,
         v2: Option<i32>,
         t: Type,
-    ) -> Status {
+    ) -> Result<Status, SheetError> {
         let (row, col) = cor;
-        assert!(
-            col < self.n && row < self.m,
-            "set_cell_equation: Invalid cell coordinates ({},{})",
-            row,
-            col
-        );
+        let cell = CellRef::new(row, col);
+        self.check_bounds(row, col)?;
+        for (r, c) in c1.iter().chain(c2.iter()) {
+            self.check_bounds(*r, *c)?;
+        }
         if t == Type::Slp {
-            assert!(
-                c1.is_none() ^ v1.is_none(),
-                "set_cell_equation: Specify either a cell coordinate or a value"
-            );
-            assert!(
-                c2.is_none() && v2.is_none(),
-                "set_cell_equation: SLP equation should not have a second operand"
-            );
+            if !(c1.is_none() ^ v1.is_none()) || c2.is_some() || v2.is_some() {
+                return Err(SheetError::InvalidOperands(cell));
+            }
             let op = match c1 {
                 Some(c) => self.cells[c.0][c.1].clone(),
                 None => SharedOperand::new(Value::new(None::<Coordinate>, v1)),
@@ -234,10 +300,17 @@ impl SpreadSheet {
             return self.set_cell_equation_from_eq(row, col, eq);
         }
 
-        assert!(
-            (c1.is_none() ^ v1.is_none()) || (c2.is_none() ^ v2.is_none()),
-            "set_cell_equation: Specify either a cell coordinate or a value"
-        );
+        if !((c1.is_none() ^ v1.is_none()) || (c2.is_none() ^ v2.is_none())) {
+            return Err(SheetError::InvalidOperands(cell));
+        }
+        if matches!(t, Type::Sum | Type::Avg | Type::Dev | Type::Min | Type::Max) {
+            let (Some(start), Some(end)) = (c1, c2) else {
+                return Err(SheetError::InvalidOperands(cell));
+            };
+            if start.0 > end.0 || start.1 > end.1 {
+                return Err(SheetError::InvalidRange(cell));
+            }
+        }
 
         let op1 = match c1 {
             Some(c) => self.cells[c.0][c.1].clone(),
@@ -253,13 +326,87 @@ impl SpreadSheet {
         self.set_cell_equation_from_eq(row, col, eq)
     }
 
-    pub fn _set_cell_value(&mut self, row: usize, col: usize, v: i32) -> Status {
-        assert!(
-            col < self.n && row < self.m,
-            "set_cell_equation: Invalid cell coordinates ({},{})",
-            row,
-            col
-        );
+    /// Sets `cell` to `operand_1 t operand_2`. `SLEEP` takes no second operand,
+    /// and a missing second operand of an arithmetic equation is treated as 0.
+    pub fn set_cell_operation(
+        &mut self,
+        cell: CellRef,
+        t: Type,
+        operand_1: Operand,
+        operand_2: Option<Operand>,
+    ) -> Result<Status, SheetError> {
+        let (c1, v1) = Operand::split(Some(operand_1));
+        let (c2, v2) = Operand::split(operand_2);
+        self.set_cell_equation(cell.into(), c1, c2, v1, v2, t)
+    }
+
+    /// Sets `cell` to a range function (`SUM`, `AVG`, `MIN`, `MAX`, `STDEV`) over `range`
+    pub fn set_cell_range_function(
+        &mut self,
+        cell: CellRef,
+        t: Type,
+        range: RangeRef,
+    ) -> Result<Status, SheetError> {
+        self.set_cell_equation(
+            cell.into(),
+            Some(range.start.into()),
+            Some(range.end.into()),
+            None,
+            None,
+            t,
+        )
+    }
+
+    /// Sets `cell` to an already parsed formula
+    pub fn set_cell_formula(
+        &mut self,
+        cell: CellRef,
+        formula: &Formula,
+    ) -> Result<Status, SheetError> {
+        match *formula {
+            Formula::Operation {
+                t,
+                operand_1,
+                operand_2,
+            } => self.set_cell_operation(cell, t, operand_1, operand_2),
+            Formula::Range { t, range } => self.set_cell_range_function(cell, t, range),
+        }
+    }
+
+    fn parse_cell(&self, cell: &str) -> Result<CellRef, SheetError> {
+        let cell = cell.trim().parse::<CellRef>()?;
+        if !self.contains(cell) {
+            return Err(SheetError::OutOfBounds(cell));
+        }
+        Ok(cell)
+    }
+
+    /// Parses and sets a formula, e.g. `set_formula("B2", "=A1*3")` or
+    /// `set_formula("C1", "SUM(A1:B2)")`. The sheet is left unchanged on error.
+    pub fn set_formula(&mut self, cell: &str, formula: &str) -> Result<(), SheetError> {
+        let cell = self.parse_cell(cell)?;
+        let formula = formula.parse::<Formula>()?;
+        match self.set_cell_formula(cell, &formula)? {
+            Status::Ok => Ok(()),
+            Status::Err => Err(SheetError::Cycle(cell)),
+        }
+    }
+
+    /// Sets a cell (e.g. `"A1"`) to a constant value
+    pub fn set_value(&mut self, cell: &str, value: i32) -> Result<(), SheetError> {
+        let cell = self.parse_cell(cell)?;
+        self.set_cell_operation(cell, Type::Add, Operand::Constant(value), None)?;
+        Ok(())
+    }
+
+    /// Sets a cell to a constant value
+    pub fn _set_cell_value(
+        &mut self,
+        row: usize,
+        col: usize,
+        v: i32,
+    ) -> Result<Status, SheetError> {
+        self.check_bounds(row, col)?;
 
         let op1 = SharedOperand::new(Value::new(None::<Coordinate>, Some(v)));
         let op2 = SharedOperand::new(Value::new(None::<Coordinate>, Some(0)));
@@ -313,7 +460,12 @@ impl SpreadSheet {
         false
     }
 
-    pub fn set_cell_equation_from_eq(&mut self, row: usize, col: usize, eq: Equation) -> Status {
+    pub(crate) fn set_cell_equation_from_eq(
+        &mut self,
+        row: usize,
+        col: usize,
+        eq: Equation,
+    ) -> Result<Status, SheetError> {
         // print!("New equation: ");
         // eq.print();
         // println!();
@@ -321,28 +473,36 @@ impl SpreadSheet {
         let cell_ref = self.cells[row][col].clone();
 
         if self.check_target_in_operands(row, col, eq.clone()) {
-            return Status::Err;
+            return Ok(Status::Err);
         }
+        // Equations that cannot be evaluated are rejected before touching the sheet, and the
+        // value is kept rather than evaluating the equation again
+        let (value, _) = eq.process_equation_silent(self)?;
 
-        let old_eq = cell_ref.borrow().get_equation().clone();
+        let old_eq = cell_ref.borrow().get_equation()?;
         cell_ref
             .borrow_mut()
-            .set_equation(eq, cell_ref.clone(), self);
+            .set_equation(eq, value, cell_ref.clone(), self)?;
 
         if !self.do_operation(row, col) {
             {
+                // The old equation may no longer evaluate, e.g. sleeping for a negative time
+                // after an upstream change, which makes the cell an ERR cell
+                let old_value = old_eq
+                    .process_equation_silent(self)
+                    .map_or(None, |(value, _)| value);
                 cell_ref
                     .borrow_mut()
-                    .set_equation(old_eq, cell_ref.clone(), self);
+                    .set_equation(old_eq, old_value, cell_ref.clone(), self)?;
             }
             // println!("set_cell_equation: Failed to set equation due to cycle, reverting to old equation");
             // print!("Old equation: ");
             // cell_ref.borrow_mut().get_equation().print();
             // println!();
-            return Status::Err;
+            return Ok(Status::Err);
         };
 
-        Status::Ok
+        Ok(Status::Ok)
     }
 
     // pub fn print(&self) {
@@ -374,15 +534,15 @@ mod tests {
     fn test_set_and_get_cell_value() {
         let mut spreadsheet = SpreadSheet::new(3, 3);
         let status = spreadsheet._set_cell_value(1, 1, 42);
-        assert_eq!(status, Status::Ok);
-        assert_eq!(spreadsheet.get_cell_value(1, 1), Some(42));
+        assert_eq!(status, Ok(Status::Ok));
+        assert_eq!(spreadsheet.get_cell_value(1, 1), Ok(Some(42)));
     }
 
     #[test]
     fn test_set_cell_equation_addition() {
         let mut spreadsheet = SpreadSheet::new(3, 3);
-        spreadsheet._set_cell_value(0, 0, 10);
-        spreadsheet._set_cell_value(0, 1, 20);
+        spreadsheet._set_cell_value(0, 0, 10).unwrap();
+        spreadsheet._set_cell_value(0, 1, 20).unwrap();
 
         let status = spreadsheet.set_cell_equation(
             (0, 2),
@@ -392,15 +552,15 @@ mod tests {
             None,
             Type::Add,
         );
-        assert_eq!(status, Status::Ok);
-        assert_eq!(spreadsheet.get_cell_value(0, 2), Some(30));
+        assert_eq!(status, Ok(Status::Ok));
+        assert_eq!(spreadsheet.get_cell_value(0, 2), Ok(Some(30)));
     }
 
     #[test]
     fn test_set_cell_equation_subtraction() {
         let mut spreadsheet = SpreadSheet::new(3, 3);
-        spreadsheet._set_cell_value(0, 0, 50);
-        spreadsheet._set_cell_value(0, 1, 20);
+        spreadsheet._set_cell_value(0, 0, 50).unwrap();
+        spreadsheet._set_cell_value(0, 1, 20).unwrap();
 
         let status = spreadsheet.set_cell_equation(
             (0, 2),
@@ -410,31 +570,149 @@ mod tests {
             None,
             Type::Sub,
         );
-        assert_eq!(status, Status::Ok);
-        assert_eq!(spreadsheet.get_cell_value(0, 2), Some(30));
+        assert_eq!(status, Ok(Status::Ok));
+        assert_eq!(spreadsheet.get_cell_value(0, 2), Ok(Some(30)));
     }
 
     #[test]
     fn test_cycle_detection() {
         let mut spreadsheet = SpreadSheet::new(3, 3);
-        spreadsheet._set_cell_value(0, 0, 10);
-        spreadsheet._set_cell_value(0, 1, 20);
+        spreadsheet._set_cell_value(0, 0, 10).unwrap();
+        spreadsheet._set_cell_value(0, 1, 20).unwrap();
 
-        spreadsheet.set_cell_equation((0, 2), Some((0, 0)), Some((0, 1)), None, None, Type::Add);
+        spreadsheet
+            .set_cell_equation((0, 2), Some((0, 0)), Some((0, 1)), None, None, Type::Add)
+            .unwrap();
 
         let status =
             spreadsheet.set_cell_equation((0, 0), Some((0, 2)), None, None, None, Type::Add);
-        assert_eq!(status, Status::Err); // Cycle detected
+        assert_eq!(status, Ok(Status::Err)); // Cycle detected
+    }
+
+    #[test]
+    fn test_cell_ref_methods() {
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        let a1: CellRef = "A1".parse().unwrap();
+        let b1: CellRef = "B1".parse().unwrap();
+        let c1: CellRef = "C1".parse().unwrap();
+
+        spreadsheet
+            .set_cell_operation(a1, Type::Add, Operand::Constant(4), None)
+            .unwrap();
+        let status = spreadsheet.set_cell_operation(
+            b1,
+            Type::Mul,
+            Operand::Cell(a1),
+            Some(Operand::Constant(2)),
+        );
+        assert_eq!(status, Ok(Status::Ok));
+        assert_eq!(spreadsheet.get_value(b1), Ok(Some(8)));
+
+        let range: RangeRef = "A1:B1".parse().unwrap();
+        let status = spreadsheet.set_cell_range_function(c1, Type::Sum, range);
+        assert_eq!(status, Ok(Status::Ok));
+        assert_eq!(spreadsheet.get_value(c1), Ok(Some(12)));
+        assert_eq!(
+            spreadsheet.get_range_values(range),
+            Ok(vec![(a1, Some(4)), (b1, Some(8))])
+        );
+        assert!(spreadsheet.contains("C3".parse().unwrap()));
+        assert!(!spreadsheet.contains("D1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_set_formula_and_value() {
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        assert_eq!(spreadsheet.set_value("A1", 42), Ok(()));
+        assert_eq!(spreadsheet.set_formula("B2", "=A1*3"), Ok(()));
+        assert_eq!(spreadsheet.set_formula("C1", "MAX(A1:B2)"), Ok(()));
+        assert_eq!(spreadsheet.get_cell_value(1, 1), Ok(Some(126)));
+        assert_eq!(spreadsheet.get_cell_value(0, 2), Ok(Some(126)));
+    }
+
+    #[test]
+    fn test_set_formula_errors() {
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_formula("B1", "A1+1").unwrap();
+        let b1: CellRef = "B1".parse().unwrap();
+
+        assert!(matches!(
+            spreadsheet.set_formula("a1", "1"),
+            Err(SheetError::InvalidReference(_))
+        ));
+        assert_eq!(
+            spreadsheet.set_value("D1", 1),
+            Err(SheetError::OutOfBounds("D1".parse().unwrap()))
+        );
+        assert_eq!(
+            spreadsheet.set_formula("A1", "SUM(A2:C4)"),
+            Err(SheetError::OutOfBounds("C4".parse().unwrap()))
+        );
+        assert_eq!(
+            spreadsheet.set_formula("A1", "A1+"),
+            Err(SheetError::InvalidFormula("A1+".to_string()))
+        );
+        assert_eq!(
+            spreadsheet.set_formula("A1", "B1*2"),
+            Err(SheetError::Cycle("A1".parse().unwrap()))
+        );
+        assert_eq!(spreadsheet.get_value(b1), Ok(Some(1)));
     }
 
     #[test]
     fn test_set_cell_equation_single_operand() {
         let mut spreadsheet = SpreadSheet::new(3, 3);
-        spreadsheet._set_cell_value(0, 0, 1);
+        spreadsheet._set_cell_value(0, 0, 1).unwrap();
 
         let status =
             spreadsheet.set_cell_equation((0, 1), Some((0, 0)), None, None, None, Type::Slp);
-        assert_eq!(status, Status::Ok);
-        assert_eq!(spreadsheet.get_cell_value(0, 1), Some(1));
+        assert_eq!(status, Ok(Status::Ok));
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(1)));
+    }
+
+    #[test]
+    fn test_invalid_input_returns_errors() {
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        let a1 = CellRef::new(0, 0);
+        assert_eq!(
+            spreadsheet.get_cell_value(3, 0),
+            Err(SheetError::OutOfBounds(CellRef::new(3, 0)))
+        );
+        assert_eq!(
+            spreadsheet.set_cell_equation((0, 0), None, None, Some(-1), None, Type::Slp),
+            Err(SheetError::NegativeSleep(a1))
+        );
+        assert_eq!(
+            spreadsheet.set_cell_equation((0, 0), Some((0, 1)), None, None, None, Type::Sum),
+            Err(SheetError::InvalidOperands(a1))
+        );
+        assert_eq!(
+            spreadsheet.set_cell_equation(
+                (0, 0),
+                Some((2, 2)),
+                Some((1, 1)),
+                None,
+                None,
+                Type::Max
+            ),
+            Err(SheetError::InvalidRange(a1))
+        );
+        assert_eq!(
+            spreadsheet.set_cell_equation((0, 0), Some((0, 5)), None, None, None, Type::Add),
+            Err(SheetError::OutOfBounds(CellRef::new(0, 5)))
+        );
+        assert!(spreadsheet.get_cell_equation_parameters(5, 5).is_err());
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(0)));
+    }
+
+    #[test]
+    fn test_overflow_and_empty_average_are_err_cells() {
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_value("A1", i32::MAX).unwrap();
+        spreadsheet.set_formula("B1", "A1+1").unwrap();
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(None));
+        spreadsheet.set_formula("A2", "1/0").unwrap();
+        spreadsheet.set_formula("B2", "AVG(A2:A2)").unwrap();
+        assert_eq!(spreadsheet.get_cell_value(1, 1), Ok(None));
     }
 }
@@ -1,6 +1,9 @@
+use crate::error::SheetError;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::fmt;
 use std::rc::Rc;
+use std::str::FromStr;
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct SerializableRcRefCell<T>(pub Rc<RefCell<T>>);
@@ -70,40 +73,47 @@ pub enum Type {
     Slp,
 }
 
-impl Type {
-    // pub fn to_str(&self) -> &str {
-    //     match self {
-    //         Type::ADD => "ADD",
-    //         Type::SUB => "SUB",
-    //         Type::MUL => "MUL",
-    //         Type::DIV => "DIV",
-    //         Type::NUL => "NUL",
-    //         Type::MIN => "MIN",
-    //         Type::MAX => "MAX",
-    //         Type::SUM => "SUM",
-    //         Type::AVG => "AVG",
-    //         Type::DEV => "DEV",
-    //         Type::SLP => "SLP",
-    //     }
-    // }
-    pub fn from_str(s: &str) -> Type {
+impl FromStr for Type {
+    type Err = SheetError;
+
+    fn from_str(s: &str) -> Result<Type, SheetError> {
         match s {
-            "+" => Type::Add,
-            "-" => Type::Sub,
-            "*" => Type::Mul,
-            "/" => Type::Div,
-            "NUL" => Type::Nul,
-            "MIN" => Type::Min,
-            "MAX" => Type::Max,
-            "SUM" => Type::Sum,
-            "AVG" => Type::Avg,
-            "STDEV" => Type::Dev,
-            "SLEEP" => Type::Slp,
-            _ => panic!("Unknown type: {}", s),
+            "+" => Ok(Type::Add),
+            "-" => Ok(Type::Sub),
+            "*" => Ok(Type::Mul),
+            "/" => Ok(Type::Div),
+            "NUL" => Ok(Type::Nul),
+            "MIN" => Ok(Type::Min),
+            "MAX" => Ok(Type::Max),
+            "SUM" => Ok(Type::Sum),
+            "AVG" => Ok(Type::Avg),
+            "STDEV" => Ok(Type::Dev),
+            "SLEEP" => Ok(Type::Slp),
+            _ => Err(SheetError::UnknownFunction(s.to_string())),
         }
     }
 }
 
+/// Writes the operator or function name accepted by `from_str`
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Add => "+",
+            Type::Sub => "-",
+            Type::Mul => "*",
+            Type::Div => "/",
+            Type::Nul => "NUL",
+            Type::Min => "MIN",
+            Type::Max => "MAX",
+            Type::Sum => "SUM",
+            Type::Avg => "AVG",
+            Type::Dev => "STDEV",
+            Type::Slp => "SLEEP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub enum Status {
     Ok,
@@ -147,22 +157,24 @@ mod tests {
 
     #[test]
     fn test_type_from_str() {
-        assert_eq!(Type::from_str("+"), Type::Add);
-        assert_eq!(Type::from_str("-"), Type::Sub);
-        assert_eq!(Type::from_str("*"), Type::Mul);
-        assert_eq!(Type::from_str("/"), Type::Div);
-        assert_eq!(Type::from_str("NUL"), Type::Nul);
-        assert_eq!(Type::from_str("MIN"), Type::Min);
-        assert_eq!(Type::from_str("MAX"), Type::Max);
-        assert_eq!(Type::from_str("SUM"), Type::Sum);
-        assert_eq!(Type::from_str("AVG"), Type::Avg);
-        assert_eq!(Type::from_str("STDEV"), Type::Dev);
-        assert_eq!(Type::from_str("SLEEP"), Type::Slp);
+        assert_eq!(Type::from_str("+"), Ok(Type::Add));
+        assert_eq!(Type::from_str("-"), Ok(Type::Sub));
+        assert_eq!(Type::from_str("*"), Ok(Type::Mul));
+        assert_eq!(Type::from_str("/"), Ok(Type::Div));
+        assert_eq!(Type::from_str("NUL"), Ok(Type::Nul));
+        assert_eq!(Type::from_str("MIN"), Ok(Type::Min));
+        assert_eq!(Type::from_str("MAX"), Ok(Type::Max));
+        assert_eq!(Type::from_str("SUM"), Ok(Type::Sum));
+        assert_eq!(Type::from_str("AVG"), Ok(Type::Avg));
+        assert_eq!(Type::from_str("STDEV"), Ok(Type::Dev));
+        assert_eq!(Type::from_str("SLEEP"), Ok(Type::Slp));
     }
 
     #[test]
-    #[should_panic(expected = "Unknown type: INVALID")]
     fn test_type_from_str_invalid() {
-        Type::from_str("INVALID");
+        assert_eq!(
+            Type::from_str("INVALID"),
+            Err(SheetError::UnknownFunction("INVALID".to_string()))
+        );
     }
 }
@@ -1,4 +1,5 @@
 use crate::equation::Equation;
+use crate::error::SheetError;
 use crate::spreadsheet::SpreadSheet;
 use crate::utils::Coordinate;
 use crate::utils::SerializableRcRefCell;
@@ -43,9 +44,11 @@ impl Cell {
         }
     }
 
+    /// Replaces the equation and its value, which the caller has already evaluated
     fn set_equation(
         &mut self,
         eq: Equation,
+        value: Option<i32>,
         self_ref: SharedOperand,
         spreadsheet_ref: &SpreadSheet,
     ) {
@@ -62,9 +65,9 @@ impl Cell {
             }
         }
 
-        self.value = eq.process_equation_silent(spreadsheet_ref).0;
+        self.value = value;
 
-        self.equation = Box::new(eq);
+        *self.equation = eq;
 
         let new_operands = self.equation.get_operands().clone();
 
@@ -177,10 +180,10 @@ impl Value {
         }
     }
 
-    pub fn get_equation(&self) -> Equation {
+    pub fn get_equation(&self) -> Result<Equation, SheetError> {
         match self {
-            Value::Cell(cell) => (*cell.equation).clone(),
-            Value::Constant(_) => panic!("Value does not have an equation!"),
+            Value::Cell(cell) => Ok((*cell.equation).clone()),
+            Value::Constant(_) => Err(SheetError::NotACell),
         }
     }
 
@@ -212,14 +215,16 @@ impl Value {
     pub fn set_equation(
         &mut self,
         eq: Equation,
+        value: Option<i32>,
         self_ref: SharedOperand,
         spreadsheet_ref: &SpreadSheet,
-    ) {
+    ) -> Result<(), SheetError> {
         match self {
             Value::Cell(cell) => {
-                cell.set_equation(eq, self_ref, spreadsheet_ref);
+                cell.set_equation(eq, value, self_ref, spreadsheet_ref);
+                Ok(())
             }
-            Value::Constant(_) => panic!("Value can't have an equation!"),
+            Value::Constant(_) => Err(SheetError::NotACell),
         }
     }
 }
@@ -302,10 +307,18 @@ mod tests {
         let spreadsheet = SpreadSheet::new(3, 3); // Assuming SpreadSheet has a new method
         let shared_operand = SharedOperand::new(Value::Cell(cell.clone()));
 
-        cell.set_equation(equation, shared_operand, &spreadsheet);
+        cell.set_equation(equation, Some(0), shared_operand, &spreadsheet);
         assert!(cell.equation.get_operands().is_empty());
     }
 
+    #[test]
+    fn test_get_equation_of_constant() {
+        let constant = Value::new(None::<Coordinate>, Some(1));
+        assert!(matches!(constant.get_equation(), Err(SheetError::NotACell)));
+        let cell = Value::new(Some(Coordinate(0, 0)), None);
+        assert!(cell.get_equation().is_ok());
+    }
+
     #[test]
     fn test_shared_operand() {
         let value = Value::new(None::<Coordinate>, Some(15));
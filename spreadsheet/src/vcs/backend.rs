@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use super::commit::CommitOptions;
+use super::error::VcsError;
+use super::vcs_engine::VersionControl;
+use super::vcs_engine_2::VersionControlSystem;
+use crate::spreadsheet::SpreadSheet;
+
+/// A commit as every backend can describe it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub id: usize,
+    /// First parent, 0 for root commits
+    pub parent: usize,
+    pub message: String,
+    pub author: Option<String>,
+}
+
+/// How a repository stores its commits. `diff` is [`VersionControl`], which supports every
+/// `gitsap` command; `snapshot` is [`VersionControlSystem`], which stores the full sheet in
+/// every commit and supports the commands of this trait.
+pub trait VcsBackend {
+    /// Commits the spreadsheet on top of HEAD, by the configured author if none is given.
+    /// Returns the lines to print, such as the output of commit hooks.
+    fn commit(
+        &mut self,
+        message: &str,
+        author: Option<&str>,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError>;
+
+    /// Rebuilds the spreadsheet at commit `id` and detaches HEAD from the current branch
+    fn checkout(&mut self, id: usize) -> Result<SpreadSheet, VcsError>;
+
+    /// Moves HEAD, and the current branch with it, to commit `id`
+    fn reset(&mut self, id: usize) -> Result<SpreadSheet, VcsError>;
+
+    /// Prints all commits
+    fn list(&self);
+
+    /// All commits, oldest first
+    fn history(&self) -> Vec<HistoryEntry>;
+
+    /// Rebuilds the spreadsheet at commit `id` without moving HEAD
+    fn spreadsheet_at(&self, id: usize) -> Result<SpreadSheet, VcsError>;
+
+    fn head(&self) -> usize;
+
+    /// The spreadsheet as of HEAD, or an empty one before the first commit
+    fn head_spreadsheet(&self) -> SpreadSheet;
+
+    fn get_m_n(&self) -> (usize, usize);
+
+    /// Fails if the working sheet has changes since HEAD that replacing it would lose
+    fn check_clean(&self, spreadsheet: &mut SpreadSheet) -> Result<(), VcsError>;
+
+    /// The diff engine with all other commands, `None` for other backends
+    fn full(&mut self) -> Option<&mut VersionControl> {
+        None
+    }
+}
+
+impl VcsBackend for VersionControl {
+    fn commit(
+        &mut self,
+        message: &str,
+        author: Option<&str>,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        let options = CommitOptions {
+            message: Some(message.to_string()),
+            author: author.map(str::to_string),
+            ..CommitOptions::default()
+        };
+        self.commit_with(&options, spreadsheet)
+    }
+
+    fn checkout(&mut self, id: usize) -> Result<SpreadSheet, VcsError> {
+        VersionControl::checkout(self, id)
+    }
+
+    fn reset(&mut self, id: usize) -> Result<SpreadSheet, VcsError> {
+        VersionControl::reset(self, id)
+    }
+
+    fn list(&self) {
+        VersionControl::list(self)
+    }
+
+    fn history(&self) -> Vec<HistoryEntry> {
+        let mut history: Vec<HistoryEntry> = self
+            .commits()
+            .iter()
+            .map(|(&id, info)| HistoryEntry {
+                id,
+                parent: info.parent,
+                message: info.message.clone(),
+                author: info.author.clone(),
+            })
+            .collect();
+        history.sort_unstable_by_key(|entry| entry.id);
+        history
+    }
+
+    fn spreadsheet_at(&self, id: usize) -> Result<SpreadSheet, VcsError> {
+        VersionControl::spreadsheet_at(self, id)
+    }
+
+    fn head(&self) -> usize {
+        VersionControl::head(self)
+    }
+
+    fn head_spreadsheet(&self) -> SpreadSheet {
+        VersionControl::head_spreadsheet(self)
+    }
+
+    fn get_m_n(&self) -> (usize, usize) {
+        VersionControl::get_m_n(self)
+    }
+
+    fn check_clean(&self, spreadsheet: &mut SpreadSheet) -> Result<(), VcsError> {
+        VersionControl::check_clean(self, spreadsheet)
+    }
+
+    fn full(&mut self) -> Option<&mut VersionControl> {
+        Some(self)
+    }
+}
+
+/// Creates an empty repository of backend `kind` (`diff` or `snapshot`) in `vcs_dir`. An
+/// existing repository there is only replaced if `force` is set.
+pub fn create(
+    kind: &str,
+    vcs_dir: String,
+    m: usize,
+    n: usize,
+    force: bool,
+) -> Result<Box<dyn VcsBackend>, VcsError> {
+    match kind {
+        "diff" => Ok(Box::new(VersionControl::new(vcs_dir, &m, &n, force)?)),
+        "snapshot" => Ok(Box::new(VersionControlSystem::new(vcs_dir, m, n, force)?)),
+        _ => Err(VcsError::UnknownBackend(kind.to_string())),
+    }
+}
+
+/// Opens the repository of backend `kind` in `vcs_dir` at its HEAD
+pub fn open(kind: &str, vcs_dir: String) -> Result<Box<dyn VcsBackend>, VcsError> {
+    match kind {
+        "diff" => {
+            let serial_vcs = super::vcs_engine::SerialVcs::load(&vcs_dir)?;
+            Ok(Box::new(VersionControl::load(serial_vcs, vcs_dir)?))
+        }
+        "snapshot" => Ok(Box::new(VersionControlSystem::load(vcs_dir)?)),
+        _ => Err(VcsError::UnknownBackend(kind.to_string())),
+    }
+}
+
+/// Recommits every commit of `from` into the empty repository `to`, oldest first and on
+/// top of the copy of its first parent, and moves `to` to the copy of HEAD. Returns the
+/// number of commits copied.
+///
+/// Messages and authors are kept, commit times are those of the migration. Only the parts
+/// both backends share are copied: merge commits keep their first parent only, and
+/// branches other than the current one, tags and stashes are left behind.
+pub fn migrate(from: &dyn VcsBackend, to: &mut dyn VcsBackend) -> Result<usize, VcsError> {
+    if from.get_m_n() != to.get_m_n() {
+        return Err(VcsError::SizeMismatch);
+    }
+    let history = from.history();
+    let mut copies = HashMap::from([(0, 0)]);
+    for entry in &history {
+        let mut spreadsheet = from.spreadsheet_at(entry.id)?;
+        // A root commit after the first one is committed on top of the previous commit
+        if let Some(&parent) = copies.get(&entry.parent)
+            && parent != 0
+            && parent != to.head()
+        {
+            to.reset(parent)?;
+        }
+        to.commit(&entry.message, entry.author.as_deref(), &mut spreadsheet)?;
+        copies.insert(entry.id, to.head());
+    }
+    if let Some(&head) = copies.get(&from.head())
+        && head != 0
+    {
+        to.reset(head)?;
+    }
+    Ok(history.len())
+}
@@ -0,0 +1,369 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use super::error::VcsError;
+use super::log::signature;
+use super::vcs_engine::VersionControl;
+use crate::cell_ref::CellRef;
+use crate::spreadsheet::{Operand, SpreadSheet};
+
+/// Comparisons a condition can use, two-character ones first so `<=` is not read as `<`
+const OPERATORS: [&str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+/// What `gitsap bisect run` checks on every commit it tests, e.g. `A10 == 500` or
+/// `B2 >= C2`. Commits where it holds are good, commits where it does not or where one of
+/// its cells is ERR are bad.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    left: Operand,
+    operator: &'static str,
+    right: Operand,
+}
+
+impl FromStr for Condition {
+    type Err = VcsError;
+
+    /// Parses `<CELL|VALUE> <OP> <CELL|VALUE>` with one of `==`, `!=`, `<`, `<=`, `>`, `>=`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || VcsError::InvalidCondition(s.to_string());
+        let (index, operator) = OPERATORS
+            .iter()
+            .find_map(|&operator| s.find(operator).map(|index| (index, operator)))
+            .ok_or_else(invalid)?;
+        let operand = |text: &str| {
+            let text = text.trim();
+            text.parse::<CellRef>()
+                .map(Operand::Cell)
+                .or_else(|_| text.parse::<i32>().map(Operand::Constant))
+                .map_err(|_| invalid())
+        };
+        Ok(Condition {
+            left: operand(&s[..index])?,
+            operator,
+            right: operand(&s[index + operator.len()..])?,
+        })
+    }
+}
+
+impl Condition {
+    /// Whether the condition holds in `spreadsheet`, never if one of its cells is ERR
+    pub fn holds(&self, spreadsheet: &SpreadSheet) -> bool {
+        let value = |operand: &Operand| match operand {
+            Operand::Cell(cell) => spreadsheet
+                .get_cell_value(cell.row, cell.col)
+                .ok()
+                .flatten(),
+            Operand::Constant(value) => Some(*value),
+        };
+        let (Some(left), Some(right)) = (value(&self.left), value(&self.right)) else {
+            return false;
+        };
+        match self.operator {
+            "==" => left == right,
+            "!=" => left != right,
+            "<=" => left <= right,
+            ">=" => left >= right,
+            "<" => left < right,
+            _ => left > right,
+        }
+    }
+
+    /// A cell of the condition that lies outside an `m` x `n` sheet, if any
+    fn outside(&self, m: usize, n: usize) -> Option<CellRef> {
+        [self.left, self.right]
+            .into_iter()
+            .find_map(|operand| match operand {
+                Operand::Cell(cell) if cell.row >= m || cell.col >= n => Some(cell),
+                _ => None,
+            })
+    }
+}
+
+/// A search for the commit that broke the sheet, saved in `vcs.json` until `gitsap bisect
+/// reset`
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub struct BisectState {
+    /// HEAD when the search started
+    pub start: usize,
+    /// Current branch when the search started
+    pub branch: Option<String>,
+    /// Commit known to be bad, the first bad commit is it or one of its ancestors
+    pub bad: Option<usize>,
+    /// Commits known to be good, the first bad commit is none of their ancestors
+    pub good: Vec<usize>,
+}
+
+/// Where a bisect stands after marking a commit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BisectStep {
+    /// No bad commit is marked yet
+    NeedsBad,
+    /// No good commit is marked yet
+    NeedsGood,
+    /// Commit `commit` is checked out for testing, `left` other commits may be the first bad one
+    Test { commit: usize, left: usize },
+    /// The first bad commit
+    Found(usize),
+}
+
+impl VersionControl {
+    /// Starts searching for the commit that broke the sheet. Mark commits with
+    /// [`bisect_mark`](Self::bisect_mark) until the first bad commit is found, then go back
+    /// with [`bisect_reset`](Self::bisect_reset).
+    pub fn bisect_start(&mut self) -> Result<(), VcsError> {
+        if self.bisect.is_some() {
+            return Err(VcsError::BisectInProgress);
+        }
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        if self.curr_commit == 0 {
+            return Err(VcsError::NoCommits);
+        }
+        self.transaction(|vcs| {
+            vcs.bisect = Some(BisectState {
+                start: vcs.curr_commit,
+                branch: vcs.current_branch.clone(),
+                bad: None,
+                good: vec![],
+            });
+            vcs.save()
+        })
+    }
+
+    /// Marks commit `id` good or bad and checks out the next commit to test
+    pub fn bisect_mark(&mut self, id: usize, good: bool) -> Result<BisectStep, VcsError> {
+        if !self.map.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        if self.bisect.is_none() {
+            return Err(VcsError::NoBisect);
+        }
+        self.transaction(|vcs| {
+            let bisect = vcs.bisect.as_mut().ok_or(VcsError::NoBisect)?;
+            if !good {
+                bisect.bad = Some(id);
+            } else if !bisect.good.contains(&id) {
+                bisect.good.push(id);
+            }
+            vcs.save()
+        })?;
+        self.bisect_next()
+    }
+
+    /// Checks out the commit whose result narrows the search down the most either way, or
+    /// returns the first bad commit once it is the only one left. The candidates are the
+    /// ancestors of the bad commit that are not ancestors of a good one.
+    pub fn bisect_next(&mut self) -> Result<BisectStep, VcsError> {
+        let bisect = self.bisect.as_ref().ok_or(VcsError::NoBisect)?;
+        let Some(bad) = bisect.bad else {
+            return Ok(BisectStep::NeedsBad);
+        };
+        if bisect.good.is_empty() {
+            return Ok(BisectStep::NeedsGood);
+        }
+        let good: HashSet<usize> = bisect
+            .good
+            .iter()
+            .flat_map(|&id| self.ancestors(id))
+            .collect();
+        if good.contains(&bad) {
+            return Err(VcsError::BisectConflict(bad));
+        }
+        let candidates: HashSet<usize> = self
+            .ancestors(bad)
+            .into_iter()
+            .filter(|id| *id != bad && !good.contains(id))
+            .collect();
+        // A bad commit leaves itself and its candidate ancestors, a good one the rest
+        let next = candidates.iter().copied().max_by_key(|&id| {
+            let below = self
+                .ancestors(id)
+                .iter()
+                .filter(|ancestor| candidates.contains(ancestor))
+                .count();
+            (below.min(candidates.len() + 1 - below), Reverse(id))
+        });
+        let Some(commit) = next else {
+            return Ok(BisectStep::Found(bad));
+        };
+        if commit != self.curr_commit {
+            self.checkout(commit)?;
+        }
+        Ok(BisectStep::Test {
+            commit,
+            left: candidates.len(),
+        })
+    }
+
+    /// Ends the bisect and goes back to the branch, or the commit, it started from
+    pub fn bisect_reset(&mut self) -> Result<SpreadSheet, VcsError> {
+        let bisect = self.bisect.clone().ok_or(VcsError::NoBisect)?;
+        let start = bisect
+            .branch
+            .as_ref()
+            .and_then(|branch| self.branches.get(branch))
+            .copied()
+            .unwrap_or(bisect.start);
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        self.transaction(|vcs| {
+            vcs.bisect = None;
+            vcs.move_head(start, bisect.branch)
+        })
+    }
+
+    /// The bisect in progress, if any
+    pub fn bisect_state(&self) -> Option<&BisectState> {
+        self.bisect.as_ref()
+    }
+}
+
+/// Runs `gitsap bisect start | good [<COMMIT>] | bad [<COMMIT>] | reset | run <CONDITION>`
+/// and returns the lines to print. `good` and `bad` mark HEAD unless a commit is given.
+/// Every subcommand but `start` may check out another commit.
+pub fn bisect(vcs: &mut VersionControl, arguments: &[String]) -> Result<Vec<String>, VcsError> {
+    match arguments.first().map(String::as_str) {
+        Some("start") => {
+            vcs.bisect_start()?;
+            Ok(vec![describe(vcs, BisectStep::NeedsBad)])
+        }
+        Some(mark @ ("good" | "bad")) => {
+            let id = match arguments.get(1) {
+                Some(revision) => vcs.resolve_commit(revision)?,
+                None => vcs.head(),
+            };
+            let step = vcs.bisect_mark(id, mark == "good")?;
+            Ok(vec![describe(vcs, step)])
+        }
+        Some("reset") => {
+            vcs.bisect_reset()?;
+            Ok(vec![format!("Back at commit {}", vcs.head())])
+        }
+        Some("run") => {
+            let condition: Condition = arguments[1..].join(" ").parse()?;
+            let (m, n) = vcs.get_m_n();
+            if let Some(cell) = condition.outside(m, n) {
+                return Err(VcsError::InvalidCell(cell.to_string()));
+            }
+            run(vcs, &condition)
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+/// Tests every commit the bisect checks out, marking it good if `condition` holds and bad
+/// otherwise, until the first bad commit is found. A good and a bad commit have to be
+/// marked first.
+fn run(vcs: &mut VersionControl, condition: &Condition) -> Result<Vec<String>, VcsError> {
+    let mut lines = vec![];
+    let mut step = vcs.bisect_next()?;
+    loop {
+        match step {
+            BisectStep::NeedsBad => return Err(VcsError::BisectNeeds("bad".to_string())),
+            BisectStep::NeedsGood => return Err(VcsError::BisectNeeds("good".to_string())),
+            BisectStep::Found(_) => {
+                lines.push(describe(vcs, step));
+                return Ok(lines);
+            }
+            BisectStep::Test { commit, .. } => {
+                let good = condition.holds(&vcs.head_spreadsheet());
+                lines.push(format!(
+                    "Commit {} is {}",
+                    commit,
+                    if good { "good" } else { "bad" }
+                ));
+                step = vcs.bisect_mark(commit, good)?;
+            }
+        }
+    }
+}
+
+/// What to do next, or the first bad commit with its message, author and date
+fn describe(vcs: &VersionControl, step: BisectStep) -> String {
+    match step {
+        BisectStep::NeedsBad => "Mark a bad commit with `gitsap bisect bad [<COMMIT>]`".to_string(),
+        BisectStep::NeedsGood => {
+            "Mark a good commit with `gitsap bisect good [<COMMIT>]`".to_string()
+        }
+        BisectStep::Test { commit, left } => format!(
+            "Bisecting: {} commits left to test, checked out commit {}",
+            left, commit
+        ),
+        BisectStep::Found(id) => {
+            let info = &vcs.commits()[&id];
+            match signature(info) {
+                Some(signature) => {
+                    format!("First bad commit: {} {} - {}", id, info.message, signature)
+                }
+                None => format!("First bad commit: {} {}", id, info.message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_bisect() {
+        let args = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            "A10 >= -5".parse(),
+            Ok(Condition {
+                left: Operand::Cell(CellRef::new(9, 0)),
+                operator: ">=",
+                right: Operand::Constant(-5),
+            })
+        );
+        assert!(matches!(
+            "A10 = 500".parse::<Condition>(),
+            Err(VcsError::InvalidCondition(_))
+        ));
+
+        let dir = TempRepo::new("bisect");
+        let mut vcs = VersionControl::new(dir.name(), &3, &3, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_formula("B1", "A1*10").unwrap();
+        for value in 1..=8 {
+            spreadsheet.set_value("A1", value).unwrap();
+            vcs.commit(&format!("set_{}", value), &mut spreadsheet)
+                .unwrap();
+        }
+        assert_eq!(bisect(&mut vcs, &args("good 1")), Err(VcsError::NoBisect));
+
+        // B1 goes over 50 in commit 6
+        bisect(&mut vcs, &args("start")).unwrap();
+        assert_eq!(
+            bisect(&mut vcs, &args("run B1 <= 50")),
+            Err(VcsError::BisectNeeds("bad".to_string()))
+        );
+        bisect(&mut vcs, &args("bad")).unwrap();
+        assert_eq!(
+            bisect(&mut vcs, &args("good 1")),
+            Ok(vec![
+                "Bisecting: 6 commits left to test, checked out commit 4".to_string()
+            ])
+        );
+        assert_eq!(vcs.head(), 4);
+        let lines = bisect(&mut vcs, &args("run B1 <= 50")).unwrap();
+        assert_eq!(
+            lines[..3],
+            ["Commit 4 is good", "Commit 6 is bad", "Commit 5 is good"]
+        );
+        assert!(lines[3].starts_with("First bad commit: 6 set_6"));
+        assert_eq!(
+            bisect(&mut vcs, &args("start")),
+            Err(VcsError::BisectInProgress)
+        );
+
+        bisect(&mut vcs, &args("reset")).unwrap();
+        assert_eq!(vcs.head(), 8);
+        assert_eq!(vcs.current_branch(), Some("main"));
+        assert!(vcs.bisect_state().is_none());
+    }
+}
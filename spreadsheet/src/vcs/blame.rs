@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::Path;
+
+use super::error::VcsError;
+use super::log::signature;
+use super::vcs_engine::{CloneSpreadSheet, SerialCell, VersionControl};
+use crate::cell_ref::{CellRef, RangeRef};
+use crate::spreadsheet::SpreadSheet;
+
+/// The commit that last changed a cell's formula
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LastChange {
+    Commit(usize),
+    /// The working sheet differs from HEAD
+    Uncommitted,
+    /// No commit has set the cell
+    Never,
+}
+
+/// One cell of [`VersionControl::blame`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlameEntry {
+    pub cell: CellRef,
+    /// The formula in the working sheet, as shown by `gitsap diff`
+    pub formula: String,
+    pub change: LastChange,
+}
+
+impl VersionControl {
+    /// Cells commit `id` changed relative to its first parent
+    fn changed_cells(&self, id: usize) -> Result<Vec<SerialCell>, VcsError> {
+        let info = self.map.get(&id).ok_or(VcsError::UnknownCommit(id))?;
+        // gc deletes the diffs of snapshotted legacy commits
+        if info.hash.is_none() && info.snapshot && !Path::new(&self.commit_path(id)).exists() {
+            let parent = match info.parent {
+                0 => CloneSpreadSheet::new(self.m, self.n),
+                parent => self.load_commit(parent)?,
+            };
+            return Ok(self.get_diff_spread(&mut self.load_commit(id)?, &parent));
+        }
+        self.read_diff(id)
+    }
+
+    /// Finds the commit that last changed the formula of each cell in `range`.
+    ///
+    /// Walks the first-parent chain from HEAD through each commit's diff. A merge commit that
+    /// took a cell from the merged commit is skipped in favor of the commit on the merged
+    /// side that made the change. Cells the working sheet changed since HEAD are reported as
+    /// uncommitted.
+    pub fn blame(
+        &self,
+        range: RangeRef,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<BlameEntry>, VcsError> {
+        if range.end.row >= self.m || range.end.col >= self.n {
+            return Err(VcsError::InvalidCell(range.to_string()));
+        }
+        let working = CloneSpreadSheet::clone_spread(spreadsheet);
+        let mut diffs: HashMap<usize, Vec<SerialCell>> = HashMap::new();
+        let mut sheets: HashMap<usize, CloneSpreadSheet> = HashMap::new();
+
+        let mut entries = vec![];
+        for cell in range.cells() {
+            let current = &working.cells[cell.row][cell.col];
+            let mut change = LastChange::Never;
+            if !current.compare(&self.spread_sheet.cells[cell.row][cell.col]) {
+                change = LastChange::Uncommitted;
+            }
+            let mut commit = self.curr_commit;
+            while change == LastChange::Never && commit != 0 {
+                if let Entry::Vacant(entry) = diffs.entry(commit) {
+                    entry.insert(self.changed_cells(commit)?);
+                }
+                let info = &self.map[&commit];
+                let Some(changed) = diffs[&commit]
+                    .iter()
+                    .find(|changed| (changed.row, changed.col) == (cell.row, cell.col))
+                else {
+                    commit = info.parent;
+                    continue;
+                };
+                if let Some(merged) = info.merge_parent {
+                    if let Entry::Vacant(entry) = sheets.entry(merged) {
+                        entry.insert(self.load_commit(merged)?);
+                    }
+                    if sheets[&merged].cells[cell.row][cell.col].compare(changed) {
+                        commit = merged;
+                        continue;
+                    }
+                }
+                change = LastChange::Commit(commit);
+            }
+            entries.push(BlameEntry {
+                cell,
+                formula: current.describe(),
+                change,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+/// Renders `gitsap blame <CELL|RANGE>`, one line per cell with its formula and the commit
+/// that last changed it, e.g. `A5: SUM(A1:A4) <- 3 fixed_totals - alice, 2025-04-01 14:30`
+pub fn blame(
+    vcs: &VersionControl,
+    range: &str,
+    spreadsheet: &mut SpreadSheet,
+) -> Result<Vec<String>, VcsError> {
+    let range = range
+        .parse::<RangeRef>()
+        .map_err(|_| VcsError::InvalidCell(range.to_string()))?;
+    let entries = vcs.blame(range, spreadsheet)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let change = match entry.change {
+                LastChange::Commit(id) => {
+                    let info = &vcs.commits()[&id];
+                    match signature(info) {
+                        Some(signature) => format!("{} {} - {}", id, info.message, signature),
+                        None => format!("{} {}", id, info.message),
+                    }
+                }
+                LastChange::Uncommitted => "not committed yet".to_string(),
+                LastChange::Never => "never set".to_string(),
+            };
+            format!("{}: {} <- {}", entry.cell, entry.formula, change)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_blame() {
+        let dir = TempRepo::new("blame");
+        let mut vcs = VersionControl::new(dir.name(), &3, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 2);
+        spreadsheet.set_value("A1", 1).unwrap();
+        spreadsheet.set_value("A2", 2).unwrap();
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        vcs.create_branch("other").unwrap();
+        spreadsheet.set_value("A1", 3).unwrap();
+        vcs.commit("ours", &mut spreadsheet).unwrap();
+
+        let mut spreadsheet = vcs.switch("other").unwrap();
+        spreadsheet.set_formula("A2", "A1*2").unwrap();
+        vcs.commit("theirs", &mut spreadsheet).unwrap();
+        vcs.switch("main").unwrap();
+        let mut spreadsheet = vcs.merge(3).unwrap().0;
+        spreadsheet.set_value("A3", 9).unwrap();
+
+        let entries = vcs
+            .blame("A1:A3".parse().unwrap(), &mut spreadsheet)
+            .unwrap();
+        let changes: Vec<LastChange> = entries.iter().map(|entry| entry.change).collect();
+        // A2 came in through the merge but was written by commit 3
+        assert_eq!(
+            changes,
+            vec![
+                LastChange::Commit(2),
+                LastChange::Commit(3),
+                LastChange::Uncommitted
+            ]
+        );
+        let mut spreadsheet = vcs.head_spreadsheet();
+        let lines = blame(&vcs, "A2:B2", &mut spreadsheet).unwrap();
+        assert!(lines[0].starts_with("A2: A1*2 <- 3 theirs - "));
+        assert_eq!(lines[1], "B2: (empty) <- never set");
+        assert_eq!(
+            blame(&vcs, "A4", &mut spreadsheet),
+            Err(VcsError::InvalidCell("A4:A4".to_string()))
+        );
+    }
+}
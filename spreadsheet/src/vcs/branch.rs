@@ -0,0 +1,102 @@
+use super::error::VcsError;
+use super::vcs_engine::{VersionControl, valid_name};
+use crate::spreadsheet::SpreadSheet;
+
+impl VersionControl {
+    /// Creates a branch pointing at the current commit, without switching to it
+    pub fn create_branch(&mut self, name: &str) -> Result<(), VcsError> {
+        if !valid_name(name) {
+            return Err(VcsError::InvalidBranchName(name.to_string()));
+        }
+        if self.branches.contains_key(name) {
+            return Err(VcsError::BranchExists(name.to_string()));
+        }
+        if self.tags.contains_key(name) {
+            return Err(VcsError::TagExists(name.to_string()));
+        }
+        if self.curr_commit == 0 {
+            return Err(VcsError::NoCommits);
+        }
+        self.transaction(|vcs| {
+            vcs.branches.insert(name.to_string(), vcs.curr_commit);
+            vcs.save()
+        })
+    }
+
+    /// Checks out the tip of a branch and makes it the current branch
+    pub fn switch(&mut self, name: &str) -> Result<SpreadSheet, VcsError> {
+        let &id = self
+            .branches
+            .get(name)
+            .ok_or_else(|| VcsError::UnknownBranch(name.to_string()))?;
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        self.transaction(|vcs| vcs.move_head(id, Some(name.to_string())))
+    }
+
+    /// All branches, the current one marked with `*`
+    pub fn list_branches(&self) -> Vec<String> {
+        let mut lines = vec![];
+        if self.current_branch.is_none() && self.curr_commit != 0 {
+            lines.push(format!("* (HEAD detached at {})", self.curr_commit));
+        }
+        for (name, commit) in &self.branches {
+            let marker = if self.current_branch.as_deref() == Some(name) {
+                '*'
+            } else {
+                ' '
+            };
+            lines.push(format!("{} {} (commit {})", marker, name, commit));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::SerialVcs;
+
+    #[test]
+    fn test_branches() {
+        let dir = TempRepo::new("branch");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        assert_eq!(vcs.create_branch("early"), Err(VcsError::NoCommits));
+        vcs.commit("Initial_commit", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.current_branch(), Some("main"));
+
+        vcs.create_branch("optimistic").unwrap();
+        assert_eq!(
+            vcs.create_branch("optimistic"),
+            Err(VcsError::BranchExists("optimistic".to_string()))
+        );
+        assert_eq!(
+            vcs.create_branch("12"),
+            Err(VcsError::InvalidBranchName("12".to_string()))
+        );
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("pessimistic", &mut spreadsheet).unwrap();
+
+        let mut spreadsheet = vcs.switch("optimistic").unwrap();
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(0)));
+        spreadsheet.set_value("A1", 100).unwrap();
+        vcs.commit("optimistic", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.branches["optimistic"], 3);
+        assert_eq!(vcs.branches["main"], 2);
+        assert_eq!(vcs.map[&3].parent, 1);
+
+        let spreadsheet = vcs.switch("main").unwrap();
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(1)));
+        assert!(vcs.switch("missing").is_err());
+
+        let serial_vcs = SerialVcs::load(&dir_name).unwrap();
+        assert_eq!(serial_vcs.head, 2);
+        assert_eq!(serial_vcs.current_branch.as_deref(), Some("main"));
+        let vcs = VersionControl::load(serial_vcs, dir_name).unwrap();
+        assert_eq!(vcs.branches.len(), 2);
+    }
+}
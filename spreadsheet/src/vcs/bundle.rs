@@ -0,0 +1,182 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::error::VcsError;
+use super::objects;
+use super::vcs_engine::{SerialVcs, VersionControl, remove_repository};
+
+const FORMAT: &str = "gitsap-bundle-1";
+
+/// Content of a bundle file, gzip-compressed JSON
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct Bundle {
+    format: String,
+    index: SerialVcs,
+    /// Object hash -> content
+    objects: BTreeMap<String, String>,
+    /// `commit_<id>.json` files of legacy commits -> content
+    files: BTreeMap<String, String>,
+}
+
+/// Packs the whole history of `vcs`, including stashes, into `file` and returns the number
+/// of commits packed
+pub fn create(vcs: &VersionControl, file: &str) -> Result<usize, VcsError> {
+    let mut bundle = Bundle {
+        format: FORMAT.to_string(),
+        index: SerialVcs::new(vcs),
+        objects: BTreeMap::new(),
+        files: BTreeMap::new(),
+    };
+    let objects_dir = format!("{}/objects", vcs.vcs_dir());
+    for entry in fs::read_dir(&objects_dir).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(hash) = name.strip_suffix(".json") {
+            bundle
+                .objects
+                .insert(hash.to_string(), fs::read_to_string(entry.path())?);
+        }
+    }
+    for entry in fs::read_dir(vcs.vcs_dir())?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if legacy_file(&name) {
+            bundle.files.insert(name, fs::read_to_string(entry.path())?);
+        }
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, &bundle).map_err(|err| VcsError::Io(err.to_string()))?;
+    objects::write_atomic(file, &encoder.finish()?)?;
+    Ok(vcs.commits().len())
+}
+
+/// Recreates the repository packed in `file` in directory `vcs_dir`, which must not hold a
+/// repository yet. Every object is checked against its hash and the result is checked like
+/// `gitsap fsck`, so a damaged bundle or one whose cells do not fit its sheet size is
+/// rejected and leaves nothing behind.
+pub fn import(file: &str, vcs_dir: &str) -> Result<VersionControl, VcsError> {
+    let bytes = fs::read(file)?;
+    let bundle: Bundle = serde_json::from_reader(GzDecoder::new(&bytes[..]))
+        .map_err(|_| VcsError::InvalidBundle(format!("`{}` is not a gitsap bundle", file)))?;
+    if bundle.format != FORMAT {
+        return Err(VcsError::InvalidBundle(format!(
+            "unsupported format `{}`",
+            bundle.format
+        )));
+    }
+    // The limits main.rs enforces on --rows and --cols
+    let (m, n) = bundle.index.dimensions();
+    if m == 0 || m > 999 || n == 0 || n > 18278 {
+        return Err(VcsError::InvalidBundle(format!(
+            "invalid sheet size {}x{}",
+            m, n
+        )));
+    }
+    for (hash, content) in &bundle.objects {
+        if objects::hash(content.as_bytes()) != *hash {
+            return Err(VcsError::InvalidBundle(format!(
+                "object {} does not match its hash",
+                hash
+            )));
+        }
+    }
+    if let Some(name) = bundle.files.keys().find(|name| !legacy_file(name)) {
+        return Err(VcsError::InvalidBundle(format!(
+            "unexpected file `{}`",
+            name
+        )));
+    }
+    if Path::new(&SerialVcs::path(vcs_dir)).exists() {
+        return Err(VcsError::RepositoryExists(vcs_dir.to_string()));
+    }
+
+    fs::create_dir_all(vcs_dir)?;
+    let vcs = write(&bundle, vcs_dir).and_then(|_| {
+        let vcs = VersionControl::load(SerialVcs::load(vcs_dir)?, vcs_dir.to_string())
+            .map_err(|err| VcsError::InvalidBundle(err.to_string()))?;
+        match vcs.fsck().into_iter().next() {
+            Some(problem) => Err(VcsError::InvalidBundle(problem.to_string())),
+            None => Ok(vcs),
+        }
+    });
+    if vcs.is_err() {
+        remove_repository(vcs_dir);
+    }
+    vcs
+}
+
+/// Writes the files of `bundle` to `vcs_dir`, the index last
+fn write(bundle: &Bundle, vcs_dir: &str) -> Result<(), VcsError> {
+    for content in bundle.objects.values() {
+        objects::write_bytes(vcs_dir, content.as_bytes())?;
+    }
+    for (name, content) in &bundle.files {
+        objects::write_atomic(&format!("{}/{}", vcs_dir, name), content.as_bytes())?;
+    }
+    let index = serde_json::to_vec(&bundle.index).map_err(|err| VcsError::Io(err.to_string()))?;
+    objects::write_atomic(&SerialVcs::path(vcs_dir), &index)?;
+    Ok(())
+}
+
+/// Whether `name` is a `commit_<id>.json` file
+fn legacy_file(name: &str) -> bool {
+    name.strip_prefix("commit_")
+        .and_then(|rest| rest.strip_suffix(".json"))
+        .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spreadsheet::SpreadSheet;
+    use crate::vcs::temp_repo::TempRepo;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dir = TempRepo::new("bundle");
+        let mut vcs = VersionControl::new(dir.file("original"), &3, &3, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        vcs.commit("Initial_commit", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("C3", 9).unwrap();
+        vcs.commit("corner", &mut spreadsheet).unwrap();
+        vcs.create_tag("v1", 2).unwrap();
+
+        assert_eq!(create(&vcs, &dir.file("model.bundle")), Ok(2));
+        let copy = import(&dir.file("model.bundle"), &dir.file("copy")).unwrap();
+        assert_eq!(copy.head(), 2);
+        assert_eq!(copy.tags(), vcs.tags());
+        assert_eq!(copy.commits(), vcs.commits());
+        assert_eq!(copy.head_spreadsheet().get_cell_value(2, 2), Ok(Some(9)));
+        assert_eq!(
+            import(&dir.file("model.bundle"), &dir.file("copy")).err(),
+            Some(VcsError::RepositoryExists(dir.file("copy")))
+        );
+
+        // A bundle claiming a smaller sheet than its cells need is rejected
+        let mut json = String::new();
+        GzDecoder::new(&fs::read(dir.file("model.bundle")).unwrap()[..])
+            .read_to_string(&mut json)
+            .unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(json.replace("\"m\":3", "\"m\":2").as_bytes())
+            .unwrap();
+        fs::write(dir.file("small.bundle"), encoder.finish().unwrap()).unwrap();
+        assert_eq!(
+            import(&dir.file("small.bundle"), &dir.file("small")).err(),
+            Some(VcsError::InvalidBundle("commit 2 is corrupt".to_string()))
+        );
+        assert!(!Path::new(&dir.file("small")).join("vcs.json").exists());
+
+        fs::write(dir.file("notes.txt"), "not a bundle").unwrap();
+        assert!(matches!(
+            import(&dir.file("notes.txt"), &dir.file("notes")),
+            Err(VcsError::InvalidBundle(_))
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+use super::error::VcsError;
+use super::hooks;
+use super::merge::MergeKind;
+use super::objects;
+use super::vcs_engine::{CloneSpreadSheet, CommitInfo, CommitObject, VersionControl};
+use crate::spreadsheet::SpreadSheet;
+
+/// Options of `gitsap commit [-m] <MSG> [--amend] [--author <NAME>] [--no-verify]`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommitOptions {
+    /// The words and `-m` values joined by spaces. `None` keeps the message when amending.
+    pub message: Option<String>,
+    /// Replace HEAD instead of committing on top of it
+    pub amend: bool,
+    /// Author instead of the configured one
+    pub author: Option<String>,
+    /// Skip the pre-commit hooks
+    pub no_verify: bool,
+}
+
+impl CommitOptions {
+    pub fn parse(args: &[String]) -> Result<Self, VcsError> {
+        let mut options = CommitOptions::default();
+        let mut words = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-m" => words.push(
+                    args.next()
+                        .ok_or_else(|| VcsError::InvalidOption(arg.clone()))?
+                        .clone(),
+                ),
+                "--author" => {
+                    let author = args
+                        .next()
+                        .filter(|author| !author.trim().is_empty())
+                        .ok_or_else(|| VcsError::InvalidOption(arg.clone()))?;
+                    options.author = Some(author.clone());
+                }
+                "--amend" => options.amend = true,
+                "--no-verify" => options.no_verify = true,
+                // Negative numbers are words
+                _ if arg.starts_with('-') && arg.parse::<i64>().is_err() => {
+                    return Err(VcsError::InvalidOption(arg.clone()));
+                }
+                _ => words.push(arg.clone()),
+            }
+        }
+        let message = words.join(" ");
+        if !message.trim().is_empty() {
+            options.message = Some(message);
+        } else if !options.amend {
+            return Err(VcsError::EmptyMessage);
+        }
+        Ok(options)
+    }
+
+    /// Options of `gitsap amend`, those of `commit` with `--amend` implied
+    pub fn parse_amend(args: &[String]) -> Result<Self, VcsError> {
+        let mut args = args.to_vec();
+        args.push("--amend".to_string());
+        Self::parse(&args)
+    }
+}
+
+impl VersionControl {
+    /// Commits the spreadsheet on top of HEAD. Finishes a merge once all conflicts are resolved.
+    /// Returns the lines to print, i.e. the output of hooks.
+    pub fn commit(
+        &mut self,
+        commit_msg: &str,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        let options = CommitOptions {
+            message: Some(commit_msg.to_string()),
+            ..CommitOptions::default()
+        };
+        self.commit_with(&options, spreadsheet)
+    }
+
+    /// Commits the spreadsheet, or with `amend` replaces HEAD as [`amend`](Self::amend) does.
+    /// Returns the lines to print.
+    pub fn commit_with(
+        &mut self,
+        options: &CommitOptions,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        if options.amend {
+            return self.amend(options, spreadsheet);
+        }
+        self.write_commit(options, spreadsheet)
+    }
+
+    /// Writes a commit on top of HEAD, or with `amend` one with HEAD's parents that HEAD and
+    /// its branch move to, keeping HEAD's message and author unless new ones are given.
+    /// Returns the output of the hooks and why a post-commit hook failed.
+    pub(super) fn write_commit(
+        &mut self,
+        options: &CommitOptions,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        if let Some(err) = &self.config_error {
+            return Err(err.clone());
+        }
+        if let Some(merge) = &self.merge {
+            if options.amend {
+                return Err(VcsError::MergeInProgress);
+            }
+            if !merge.conflicts.is_empty() {
+                return Err(VcsError::UnresolvedConflicts(merge.conflicts.len()));
+            }
+        }
+        // Hooks get the changes since HEAD, the same for pre- and post-commit hooks
+        let verify = !options.no_verify && !self.config.pre_commit.is_empty();
+        let changes = if verify || !self.config.post_commit.is_empty() {
+            self.diff(None, None, spreadsheet)?
+        } else {
+            vec![]
+        };
+        let mut lines = vec![];
+        if verify {
+            let head = self.map.get(&self.curr_commit).filter(|_| options.amend);
+            let message = options
+                .message
+                .clone()
+                .or_else(|| head.map(|head| head.message.clone()))
+                .unwrap_or_default();
+            let author = options
+                .author
+                .clone()
+                .or_else(|| head.and_then(|head| head.author.clone()))
+                .unwrap_or_else(|| self.config.author());
+            let input = hooks::input("pre-commit", None, &message, &author, &changes);
+            lines = hooks::run_all(&self.config.pre_commit, spreadsheet, &input)
+                .map_err(VcsError::HookRejected)?;
+        }
+
+        let lock = self.lock()?;
+        let mut working = CloneSpreadSheet::clone_spread(spreadsheet);
+        let (mut info, cells) = if options.amend {
+            let head = self.map.get(&self.curr_commit).ok_or(VcsError::NoCommits)?;
+            let parent = match head.parent {
+                0 => CloneSpreadSheet::new(self.m, self.n),
+                parent => self.load_commit(parent)?,
+            };
+            let message = options.message.as_deref().unwrap_or(&head.message);
+            let mut info = CommitInfo::new(head.parent, message);
+            info.merge_parent = head.merge_parent;
+            info.author = options.author.clone().or_else(|| head.author.clone());
+            (info, self.get_diff_spread(&mut working, &parent))
+        } else {
+            let message = options.message.as_deref().ok_or(VcsError::EmptyMessage)?;
+            let mut info = CommitInfo::new(self.curr_commit, message);
+            info.merge_parent = self
+                .merge
+                .as_ref()
+                .filter(|merge| merge.kind == MergeKind::Merge)
+                .map(|merge| merge.theirs);
+            info.author = options.author.clone();
+            (info, self.get_diff_spread(&mut working, &self.spread_sheet))
+        };
+        info.author = info.author.or_else(|| Some(self.config.author()));
+        info.timestamp = Some(chrono::Utc::now().timestamp());
+
+        let object = CommitObject {
+            parent: self.object_hash(info.parent),
+            merge_parent: info.merge_parent.and_then(|id| self.object_hash(id)),
+            message: info.message.clone(),
+            author: info.author.clone(),
+            timestamp: info.timestamp,
+            cells,
+            values: self
+                .config
+                .record_values
+                .then(|| working.values(spreadsheet)),
+        };
+        info.hash = Some(objects::write_object(&self.vcs_dir, &object)?);
+        self.transaction(|vcs| {
+            vcs.map.insert(vcs.next_commit, info);
+            vcs.merge = None;
+            vcs.curr_commit = vcs.next_commit;
+            vcs.next_commit += 1;
+            if let Some(branch) = &vcs.current_branch {
+                vcs.branches.insert(branch.clone(), vcs.curr_commit);
+            }
+            vcs.spread_sheet = working;
+            if vcs.needs_snapshot(vcs.curr_commit) {
+                vcs.write_snapshot(vcs.curr_commit, &vcs.spread_sheet.clone())?;
+            }
+            vcs.write_index()
+        })?;
+        drop(lock);
+
+        if !self.config.post_commit.is_empty() {
+            let info = &self.map[&self.curr_commit];
+            let author = info.author.as_deref().unwrap_or_default();
+            let input = hooks::input(
+                "post-commit",
+                Some(self.curr_commit),
+                &info.message,
+                author,
+                &changes,
+            );
+            match hooks::run_all(&self.config.post_commit, spreadsheet, &input) {
+                Ok(output) => lines.extend(output),
+                Err(reason) => lines.push(format!("post-commit hook failed: {}", reason)),
+            }
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_commit_options_and_amend() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            CommitOptions::parse(&args(&["fixed", "-m", "Q3 totals", "--author", "ada"])),
+            Ok(CommitOptions {
+                message: Some("fixed Q3 totals".to_string()),
+                amend: false,
+                author: Some("ada".to_string()),
+                no_verify: false,
+            })
+        );
+        assert_eq!(
+            CommitOptions::parse(&args(&["-5", "rows"]))
+                .unwrap()
+                .message,
+            Some("-5 rows".to_string())
+        );
+        assert_eq!(
+            CommitOptions::parse(&args(&["--author"])),
+            Err(VcsError::InvalidOption("--author".to_string()))
+        );
+        assert_eq!(CommitOptions::parse(&[]), Err(VcsError::EmptyMessage));
+
+        let dir = TempRepo::new("amend");
+        let mut vcs = VersionControl::new(dir.name(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        let amend = CommitOptions::parse(&args(&["--amend"])).unwrap();
+        assert_eq!(
+            vcs.commit_with(&amend, &mut spreadsheet),
+            Err(VcsError::NoCommits)
+        );
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 1).unwrap();
+        let options = CommitOptions::parse(&args(&["typo", "--author", "ada"])).unwrap();
+        vcs.commit_with(&options, &mut spreadsheet).unwrap();
+        assert_eq!(vcs.map[&2].author.as_deref(), Some("ada"));
+
+        spreadsheet.set_value("B1", 2).unwrap();
+        let options = CommitOptions::parse(&args(&["--amend", "-m", "set A1 and B1"])).unwrap();
+        vcs.commit_with(&options, &mut spreadsheet).unwrap();
+        assert_eq!(vcs.head(), 3);
+        assert_eq!(vcs.map[&3].parent, 1);
+        assert_eq!(vcs.map[&3].message, "set A1 and B1");
+        assert_eq!(vcs.map[&3].author.as_deref(), Some("ada"));
+        assert_eq!(vcs.branches()["main"], 3);
+        // `commit --amend` deletes the replaced commit just like `amend`
+        assert!(!vcs.map.contains_key(&2));
+        assert_eq!(
+            vcs.diff(Some(1), Some(3), &mut spreadsheet).unwrap().len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::env;
+use std::fs::File;
+use std::io::ErrorKind;
+
+use super::error::VcsError;
+use super::hooks::Hook;
+
+/// Commits between full snapshots when `snapshot_interval` is not set
+const DEFAULT_SNAPSHOT_INTERVAL: usize = 10;
+
+/// Settings read from `config.json` in the VCS directory. Every field is optional.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub struct VcsConfig {
+    /// Author recorded in new commits
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Store a full snapshot once this many diffs would have to be replayed; 0 disables snapshots
+    #[serde(default)]
+    pub snapshot_interval: Option<usize>,
+    /// Store the computed value of every cell in new commits, to be compared on checkout
+    #[serde(default)]
+    pub record_values: bool,
+    /// Checks that can veto `gitsap commit`
+    #[serde(default)]
+    pub pre_commit: Vec<Hook>,
+    /// Checks run after a commit, which report problems but cannot undo it
+    #[serde(default)]
+    pub post_commit: Vec<Hook>,
+}
+
+impl VcsConfig {
+    /// Reads `{vcs_dir}/config.json`, falling back to the defaults only if it is missing
+    pub fn load(vcs_dir: &str) -> Result<Self, VcsError> {
+        let path = format!("{}/config.json", vcs_dir);
+        let invalid = |reason: String| VcsError::InvalidConfig(path.clone(), reason);
+        match File::open(&path) {
+            Ok(file) => serde_json::from_reader(file).map_err(|err| invalid(err.to_string())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(VcsConfig::default()),
+            Err(err) => Err(invalid(err.to_string())),
+        }
+    }
+
+    pub fn snapshot_interval(&self) -> usize {
+        self.snapshot_interval.unwrap_or(DEFAULT_SNAPSHOT_INTERVAL)
+    }
+
+    /// Author of new commits: `GITSAP_AUTHOR`, then the configured author, then `USER`
+    pub fn author(&self) -> String {
+        env::var("GITSAP_AUTHOR")
+            .ok()
+            .or_else(|| self.author.clone())
+            .or_else(|| env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
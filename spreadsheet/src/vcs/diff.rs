@@ -0,0 +1,171 @@
+use std::fmt;
+
+use super::error::VcsError;
+use super::vcs_engine::{CloneSpreadSheet, SerialCell, VersionControl};
+use crate::cell_ref::CellRef;
+use crate::formula::Formula;
+use crate::spreadsheet::SpreadSheet;
+
+/// A cell whose formula differs between two versions of the sheet.
+/// Formulas are `None` for empty cells and values are `None` for ERR cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellChange {
+    pub cell: CellRef,
+    pub old_formula: Option<Formula>,
+    pub new_formula: Option<Formula>,
+    pub old_value: Option<i32>,
+    pub new_value: Option<i32>,
+}
+
+impl fmt::Display for CellChange {
+    /// Writes e.g. `A1: 5 -> B1*2 (5 -> 14)`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let formula = |formula: &Option<Formula>| match formula {
+            Some(formula) => formula.to_string(),
+            None => "(empty)".to_string(),
+        };
+        let value = |value: Option<i32>| match value {
+            Some(value) => value.to_string(),
+            None => "ERR".to_string(),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.cell,
+            formula(&self.old_formula),
+            formula(&self.new_formula)
+        )?;
+        if self.old_value != self.new_value {
+            write!(
+                f,
+                " ({} -> {})",
+                value(self.old_value),
+                value(self.new_value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl CloneSpreadSheet {
+    /// Cells whose formula differs between `old` and `new`, in row-major order.
+    /// Values are read from the matching built spreadsheets.
+    fn changes(
+        old: &CloneSpreadSheet,
+        old_sheet: &SpreadSheet,
+        new: &CloneSpreadSheet,
+        new_sheet: &SpreadSheet,
+    ) -> Vec<CellChange> {
+        let value = |sheet: &SpreadSheet, cell: &SerialCell| {
+            sheet.get_cell_value(cell.row, cell.col).unwrap_or_default()
+        };
+        old.cells
+            .iter()
+            .flatten()
+            .zip(new.cells.iter().flatten())
+            .filter(|(old_cell, new_cell)| !old_cell.compare(new_cell))
+            .map(|(old_cell, new_cell)| CellChange {
+                cell: CellRef::new(new_cell.row, new_cell.col),
+                old_formula: old_cell.formula(),
+                new_formula: new_cell.formula(),
+                old_value: value(old_sheet, old_cell),
+                new_value: value(new_sheet, new_cell),
+            })
+            .collect()
+    }
+}
+
+impl VersionControl {
+    /// Changes from commit `from` (default HEAD) to commit `to` (default the working sheet)
+    pub fn diff(
+        &self,
+        from: Option<usize>,
+        to: Option<usize>,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<CellChange>, VcsError> {
+        for id in from.iter().chain(to.iter()) {
+            if !self.map.contains_key(id) {
+                return Err(VcsError::UnknownCommit(*id));
+            }
+        }
+        let old = match from {
+            Some(id) => self.load_commit(id)?,
+            None => self.spread_sheet.clone(),
+        };
+        let old_sheet = old.build(self.m, self.n);
+        let changes = match to {
+            Some(id) => {
+                let new = self.load_commit(id)?;
+                CloneSpreadSheet::changes(&old, &old_sheet, &new, &new.build(self.m, self.n))
+            }
+            None => {
+                let new = CloneSpreadSheet::clone_spread(spreadsheet);
+                CloneSpreadSheet::changes(&old, &old_sheet, &new, spreadsheet)
+            }
+        };
+        Ok(changes)
+    }
+
+    /// The current branch, any merge in progress, and the cells changed since HEAD
+    pub fn status(&self, spreadsheet: &mut SpreadSheet) -> Vec<String> {
+        let mut lines = vec![match (&self.current_branch, self.curr_commit) {
+            (Some(branch), _) => format!("On branch {}", branch),
+            (None, commit) => format!("HEAD detached at {}", commit),
+        }];
+        if let Some(merge) = &self.merge {
+            lines.push(format!(
+                "{} commit {}, {} conflicts left",
+                merge.kind.in_progress(),
+                merge.theirs,
+                merge.conflicts.len()
+            ));
+        }
+        // Diffing HEAD against the working sheet reads no commits
+        let changes = self.diff(None, None, spreadsheet).unwrap_or_default();
+        if changes.is_empty() {
+            lines.push("Nothing to commit".to_string());
+        }
+        lines.extend(changes.iter().map(|change| format!("modified {}", change)));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_diff() {
+        let dir = TempRepo::new("diff");
+        let mut vcs = VersionControl::new(dir.name(), &3, &3, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_value("A1", 5).unwrap();
+        vcs.commit("first", &mut spreadsheet).unwrap();
+        spreadsheet.set_formula("B1", "A1*2").unwrap();
+        vcs.commit("second", &mut spreadsheet).unwrap();
+
+        assert_eq!(vcs.diff(None, None, &mut spreadsheet), Ok(vec![]));
+        spreadsheet.set_value("A1", 7).unwrap();
+        let changes = vcs.diff(None, None, &mut spreadsheet).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].to_string(), "A1: 5 -> 7 (5 -> 7)");
+
+        let changes = vcs.diff(Some(1), Some(2), &mut spreadsheet).unwrap();
+        assert_eq!(
+            changes,
+            vec![CellChange {
+                cell: CellRef::new(0, 1),
+                old_formula: None,
+                new_formula: Some("A1*2".parse().unwrap()),
+                old_value: Some(0),
+                new_value: Some(10),
+            }]
+        );
+        assert_eq!(changes[0].to_string(), "B1: (empty) -> A1*2 (0 -> 10)");
+        assert_eq!(
+            vcs.diff(Some(7), None, &mut spreadsheet),
+            Err(VcsError::UnknownCommit(7))
+        );
+    }
+}
@@ -0,0 +1,252 @@
+use crate::cell_ref::CellRef;
+
+use std::fmt;
+
+/// Errors returned by `gitsap` commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VcsError {
+    /// No commit with this id exists
+    UnknownCommit(usize),
+    /// No branch with this name exists
+    UnknownBranch(String),
+    /// A branch with this name already exists
+    BranchExists(String),
+    /// Branch names are letters, digits, `-` and `_`, and cannot be all digits
+    InvalidBranchName(String),
+    /// No tag with this name exists
+    UnknownTag(String),
+    /// A tag with this name already exists
+    TagExists(String),
+    /// Tag names follow the rules for branch names
+    InvalidTagName(String),
+    /// The repository has no commits yet
+    NoCommits,
+    /// The argument is not a commit id, branch or tag
+    UnknownRevision(String),
+    /// The argument is not a cell in A1 notation
+    InvalidCell(String),
+    /// A merge is waiting for its conflicts to be resolved
+    MergeInProgress,
+    /// There is no merge to resolve conflicts of
+    NoMergeInProgress,
+    /// The cell has no merge conflict
+    NotInConflict(CellRef),
+    /// The chosen formula could not be set, e.g. because it creates a cycle
+    CannotApply(CellRef),
+    /// Committing a merge that still has this many conflicts
+    UnresolvedConflicts(usize),
+    /// An option the command does not understand, or one missing its value
+    InvalidOption(String),
+    /// The files of this commit are gone
+    MissingCommit(usize),
+    /// This commit's object does not match its hash or its recorded parents
+    CorruptCommit(usize),
+    /// The snapshot of this commit is missing or does not match its hash
+    CorruptSnapshot(usize),
+    /// A parent of this commit does not exist
+    MissingParent(usize),
+    /// The branch points to a commit that does not exist
+    DanglingBranch(String),
+    /// The tag names a commit that does not exist
+    DanglingTag(String),
+    /// Initializing over the repository in this directory without forcing
+    RepositoryExists(String),
+    /// There is no `vcs.json` in this directory
+    MissingIndex(String),
+    /// This `vcs.json` cannot be parsed
+    CorruptIndex(String),
+    /// Another process, with this id if it was recorded, holds this lock file
+    Locked(String, Option<u32>),
+    /// Another process wrote to the repository since this one opened it
+    RepositoryChanged,
+    /// This `config.json` cannot be read or parsed, for this reason
+    InvalidConfig(String, String),
+    /// Reading or writing a repository file failed
+    Io(String),
+    /// Committing without a message
+    EmptyMessage,
+    /// Replacing a working sheet with this many changed cells without forcing
+    UncommittedChanges(usize),
+    /// Stashing a working sheet without changes
+    NoChanges,
+    /// There are no stashed changes
+    NoStash,
+    /// The latest stash cannot be read
+    CorruptStash,
+    /// The cell was changed differently since it was stashed
+    StashConflict(CellRef),
+    /// No remote with this name exists
+    UnknownRemote(String),
+    /// A remote with this name already exists
+    RemoteExists(String),
+    /// Remote names follow the rules for branch names
+    InvalidRemoteName(String),
+    /// Pushing or pulling without naming a remote while there is not exactly one
+    NoDefaultRemote,
+    /// Pushing or pulling while HEAD is not on a branch
+    DetachedHead,
+    /// The other repository has a sheet of a different size
+    SizeMismatch,
+    /// This commit was made before commits were hashed and cannot be sent to another
+    /// repository
+    UnhashedCommit(usize),
+    /// The remote branch has commits that are not in this repository
+    PushRejected(String),
+    /// The file is not a bundle written by `gitsap bundle create`, or its content is damaged
+    InvalidBundle(String),
+    /// The storage backend is neither `diff` nor `snapshot`
+    UnknownBackend(String),
+    /// The command only works with the diff backend
+    NeedsDiffBackend(String),
+    /// A pre-commit hook vetoed the commit for this reason
+    HookRejected(String),
+    /// Why the commits given to `gitsap squash` cannot be combined
+    CannotSquash(String),
+    /// `gitsap bisect` was used without `gitsap bisect start`
+    NoBisect,
+    /// `gitsap bisect start` was used while a bisect is going on
+    BisectInProgress,
+    /// The commit marked bad is an ancestor of a commit marked good
+    BisectConflict(usize),
+    /// `gitsap bisect run` was used before a commit of this kind (`good` or `bad`) was marked
+    BisectNeeds(String),
+    /// A condition of `gitsap bisect run` that is not `<CELL|VALUE> <OP> <CELL|VALUE>`
+    InvalidCondition(String),
+}
+
+impl fmt::Display for VcsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VcsError::UnknownCommit(id) => write!(f, "commit {} not found", id),
+            VcsError::UnknownBranch(name) => write!(f, "branch `{}` not found", name),
+            VcsError::BranchExists(name) => write!(f, "branch `{}` already exists", name),
+            VcsError::InvalidBranchName(name) => write!(f, "invalid branch name `{}`", name),
+            VcsError::UnknownTag(name) => write!(f, "tag `{}` not found", name),
+            VcsError::TagExists(name) => write!(f, "tag `{}` already exists", name),
+            VcsError::InvalidTagName(name) => write!(f, "invalid tag name `{}`", name),
+            VcsError::NoCommits => write!(f, "no commits yet"),
+            VcsError::UnknownRevision(name) => {
+                write!(f, "no commit, branch or tag named `{}`", name)
+            }
+            VcsError::InvalidCell(cell) => write!(f, "invalid cell `{}`", cell),
+            VcsError::MergeInProgress => write!(
+                f,
+                "a merge is in progress, finish it with `gitsap commit <MSG>` or abandon it with `gitsap reset <COMMIT_ID> --force`"
+            ),
+            VcsError::NoMergeInProgress => write!(f, "no merge in progress"),
+            VcsError::NotInConflict(cell) => write!(f, "{} has no merge conflict", cell),
+            VcsError::CannotApply(cell) => write!(f, "cannot set {} without a cycle", cell),
+            VcsError::UnresolvedConflicts(count) => {
+                write!(f, "{} merge conflicts are not resolved", count)
+            }
+            VcsError::InvalidOption(option) => write!(f, "invalid option `{}`", option),
+            VcsError::MissingCommit(id) => write!(f, "commit {} is missing", id),
+            VcsError::CorruptCommit(id) => write!(f, "commit {} is corrupt", id),
+            VcsError::CorruptSnapshot(id) => write!(f, "snapshot of commit {} is corrupt", id),
+            VcsError::MissingParent(id) => write!(f, "a parent of commit {} is missing", id),
+            VcsError::DanglingBranch(name) => {
+                write!(f, "branch `{}` points to a missing commit", name)
+            }
+            VcsError::DanglingTag(name) => write!(f, "tag `{}` names a missing commit", name),
+            VcsError::RepositoryExists(dir) => write!(
+                f,
+                "a repository already exists in `{}`, use --force to overwrite it",
+                dir
+            ),
+            VcsError::MissingIndex(dir) => write!(f, "no repository found in `{}`", dir),
+            VcsError::CorruptIndex(path) => write!(f, "`{}` is corrupt", path),
+            VcsError::Locked(path, Some(pid)) => write!(
+                f,
+                "process {} holds `{}`, remove it if that process is not gitsap",
+                pid, path
+            ),
+            VcsError::Locked(path, None) => write!(
+                f,
+                "another gitsap process is writing the repository, remove `{}` if none is running",
+                path
+            ),
+            VcsError::RepositoryChanged => write!(
+                f,
+                "the repository was changed by another process, run `gitsap reload` and try again"
+            ),
+            VcsError::InvalidConfig(path, reason) => {
+                write!(f, "`{}` is invalid, fix or remove it: {}", path, reason)
+            }
+            VcsError::Io(err) => write!(f, "{}", err),
+            VcsError::EmptyMessage => write!(f, "commit message is empty"),
+            VcsError::UncommittedChanges(count) => write!(
+                f,
+                "{} uncommitted cell changes would be lost, commit or stash them, or use --force",
+                count
+            ),
+            VcsError::NoChanges => write!(f, "no uncommitted changes"),
+            VcsError::NoStash => write!(f, "no stashed changes"),
+            VcsError::CorruptStash => write!(f, "the latest stash is corrupt"),
+            VcsError::StashConflict(cell) => {
+                write!(f, "{} was changed since it was stashed, stash kept", cell)
+            }
+            VcsError::UnknownRemote(name) => write!(f, "remote `{}` not found", name),
+            VcsError::RemoteExists(name) => write!(f, "remote `{}` already exists", name),
+            VcsError::InvalidRemoteName(name) => write!(f, "invalid remote name `{}`", name),
+            VcsError::NoDefaultRemote => write!(f, "name the remote to use"),
+            VcsError::DetachedHead => write!(f, "HEAD is not on a branch"),
+            VcsError::SizeMismatch => write!(f, "the repositories have sheets of different sizes"),
+            VcsError::UnhashedCommit(id) => write!(
+                f,
+                "commit {} predates commit hashes and cannot be exchanged",
+                id
+            ),
+            VcsError::PushRejected(branch) => write!(
+                f,
+                "`{}` has commits on the remote that are not here, pull first",
+                branch
+            ),
+            VcsError::InvalidBundle(reason) => write!(f, "invalid bundle: {}", reason),
+            VcsError::HookRejected(reason) => {
+                write!(f, "commit rejected by pre-commit hook: {}", reason)
+            }
+            VcsError::CannotSquash(reason) => write!(f, "cannot squash: {}", reason),
+            VcsError::NoBisect => {
+                write!(
+                    f,
+                    "no bisect in progress, start one with `gitsap bisect start`"
+                )
+            }
+            VcsError::BisectInProgress => {
+                write!(
+                    f,
+                    "a bisect is in progress, end it with `gitsap bisect reset`"
+                )
+            }
+            VcsError::BisectConflict(id) => write!(
+                f,
+                "commit {} is marked bad but a commit marked good descends from it",
+                id
+            ),
+            VcsError::BisectNeeds(kind) => write!(
+                f,
+                "mark a {} commit with `gitsap bisect {}` first",
+                kind, kind
+            ),
+            VcsError::InvalidCondition(condition) => write!(
+                f,
+                "invalid condition `{}`, use e.g. `A10 == 500` or `B2 >= C2`",
+                condition
+            ),
+            VcsError::NeedsDiffBackend(command) => {
+                write!(f, "`gitsap {}` needs the diff backend", command)
+            }
+            VcsError::UnknownBackend(name) => {
+                write!(f, "unknown backend `{}`, use `diff` or `snapshot`", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VcsError {}
+
+impl From<std::io::Error> for VcsError {
+    fn from(err: std::io::Error) -> Self {
+        VcsError::Io(err.to_string())
+    }
+}
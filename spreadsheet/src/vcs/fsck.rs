@@ -0,0 +1,96 @@
+use super::error::VcsError;
+use super::vcs_engine::VersionControl;
+
+impl VersionControl {
+    /// Checks that every commit and snapshot can be read and matches its hash, and that
+    /// parents and branches point to existing commits. Returns the problems found.
+    pub fn fsck(&self) -> Vec<VcsError> {
+        let mut ids: Vec<usize> = self.map.keys().copied().collect();
+        ids.sort_unstable();
+
+        let mut problems = vec![];
+        for id in ids {
+            let info = &self.map[&id];
+            for parent in self.parents(id) {
+                if !self.map.contains_key(&parent) {
+                    problems.push(VcsError::MissingParent(id));
+                }
+            }
+            // gc deletes the diffs of snapshotted legacy commits
+            if (info.hash.is_some() || !info.snapshot)
+                && let Err(err) = self.read_diff(id)
+            {
+                problems.push(err);
+            }
+            if info.snapshot
+                && let Err(err) = self.read_snapshot(id)
+            {
+                problems.push(err);
+            }
+        }
+        for (name, id) in &self.branches {
+            if !self.map.contains_key(id) {
+                problems.push(VcsError::DanglingBranch(name.clone()));
+            }
+        }
+        for (name, id) in &self.tags {
+            if !self.map.contains_key(id) {
+                problems.push(VcsError::DanglingTag(name.clone()));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spreadsheet::SpreadSheet;
+    use crate::vcs::objects;
+    use crate::vcs::temp_repo::TempRepo;
+    use std::fs;
+
+    #[test]
+    fn test_hashes_detect_tampering() {
+        let dir = TempRepo::new("fsck");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("first", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 2).unwrap();
+        vcs.commit("second", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.fsck(), vec![]);
+
+        let hash = vcs.map[&1].hash.clone().unwrap();
+        let path = format!("{}/objects/{}.json", dir_name, hash);
+        let tampered = fs::read_to_string(&path).unwrap().replace("first", "frist");
+        fs::write(&path, &tampered).unwrap();
+        assert_eq!(vcs.checkout(1).err(), Some(VcsError::CorruptCommit(1)));
+
+        // Storing the edit under its new hash still breaks the hash the second commit
+        // recorded for its parent
+        let path = format!(
+            "{}/objects/{}.json",
+            dir_name,
+            objects::hash(tampered.as_bytes())
+        );
+        fs::write(&path, &tampered).unwrap();
+        let info = vcs.map.get_mut(&1).unwrap();
+        info.hash = Some(objects::hash(tampered.as_bytes()));
+        info.message = "frist".to_string();
+        assert_eq!(vcs.checkout(1).map(|_| ()), Ok(()));
+        assert_eq!(vcs.fsck(), vec![VcsError::CorruptCommit(2)]);
+
+        fs::remove_file(&path).unwrap();
+        vcs.branches.insert("gone".to_string(), 9);
+        assert_eq!(
+            vcs.fsck(),
+            vec![
+                VcsError::MissingCommit(1),
+                VcsError::CorruptCommit(2),
+                VcsError::DanglingBranch("gone".to_string())
+            ]
+        );
+    }
+}
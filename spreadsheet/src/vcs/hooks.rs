@@ -0,0 +1,225 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::diff::CellChange;
+use crate::cell_ref::{CellRef, RangeRef};
+use crate::spreadsheet::SpreadSheet;
+use crate::utils::Type;
+
+/// A check run around `gitsap commit`, configured in `config.json`, e.g.
+/// `{"pre_commit": ["no_errors", {"non_empty": "A1:A10"}, {"run": "./check.sh"}]}`
+#[derive(Clone, Debug, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+    /// Fails if a cell evaluates to ERR
+    NoErrors,
+    /// Fails if a cell of this cell or range is empty
+    NonEmpty(String),
+    /// Runs an executable with the commit as JSON on stdin, failing if it exits with an error
+    Run(String),
+}
+
+impl Hook {
+    /// Checks the sheet being committed, returning what an executable printed or why the
+    /// hook rejects it
+    fn check(&self, spreadsheet: &SpreadSheet, input: &str) -> Result<Option<String>, String> {
+        match self {
+            Hook::NoErrors => {
+                let errors: Vec<String> = (0..spreadsheet.m)
+                    .flat_map(|row| (0..spreadsheet.n).map(move |col| (row, col)))
+                    .filter(|&(row, col)| spreadsheet.get_cell_value(row, col) == Ok(None))
+                    .map(|cell| CellRef::from(cell).to_string())
+                    .collect();
+                if errors.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(format!("ERR in {}", errors.join(", ")))
+                }
+            }
+            Hook::NonEmpty(range) => {
+                let range = range
+                    .parse::<RangeRef>()
+                    .map_err(|_| format!("invalid range `{}` in config.json", range))?;
+                let empty: Vec<String> = range
+                    .cells()
+                    .filter(|cell| {
+                        !matches!(
+                            spreadsheet.get_cell_equation_parameters(cell.row, cell.col),
+                            Ok(params) if params.equation_type != Type::Nul
+                        )
+                    })
+                    .map(|cell| cell.to_string())
+                    .collect();
+                if empty.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(format!("{} must not be empty", empty.join(", ")))
+                }
+            }
+            Hook::Run(program) => run(program, input),
+        }
+    }
+}
+
+/// Runs `program` with `input` on stdin. Returns its output, which is the reason given when
+/// it exits with an error.
+fn run(program: &str, input: &str) -> Result<Option<String>, String> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("cannot run `{}`: {}", program, err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that does not read its input closes the pipe early
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("cannot run `{}`: {}", program, err))?;
+    let text = String::from_utf8_lossy(&output.stdout).to_string()
+        + &String::from_utf8_lossy(&output.stderr);
+    let text = text.trim();
+    if output.status.success() {
+        Ok((!text.is_empty()).then(|| text.to_string()))
+    } else if text.is_empty() {
+        Err(format!("`{}` failed with {}", program, output.status))
+    } else {
+        Err(text.to_string())
+    }
+}
+
+/// The JSON hook executables get on stdin: the hook, the commit (`null` before it is made),
+/// its message and author, and every changed cell with its old and new formula and value
+pub fn input(
+    hook: &str,
+    commit: Option<usize>,
+    message: &str,
+    author: &str,
+    changes: &[CellChange],
+) -> String {
+    let changes: Vec<serde_json::Value> = changes
+        .iter()
+        .map(|change| {
+            serde_json::json!({
+                "cell": change.cell.to_string(),
+                "old_formula": change.old_formula.as_ref().map(ToString::to_string),
+                "new_formula": change.new_formula.as_ref().map(ToString::to_string),
+                "old_value": change.old_value,
+                "new_value": change.new_value,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "hook": hook,
+        "commit": commit,
+        "message": message,
+        "author": author,
+        "changes": changes,
+    })
+    .to_string()
+}
+
+/// Runs `hooks` in order and returns what their executables printed, or stops at the first
+/// that fails and returns its reason
+pub fn run_all(
+    hooks: &[Hook],
+    spreadsheet: &SpreadSheet,
+    input: &str,
+) -> Result<Vec<String>, String> {
+    let mut output = vec![];
+    for hook in hooks {
+        output.extend(hook.check(spreadsheet, input)?);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::commit::CommitOptions;
+    use crate::vcs::error::VcsError;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::VersionControl;
+    use std::fs;
+
+    #[test]
+    fn test_builtin_hooks() {
+        let hooks: Vec<Hook> =
+            serde_json::from_str(r#"["no_errors", {"non_empty": "A1:B1"}]"#).unwrap();
+        assert_eq!(hooks, [Hook::NoErrors, Hook::NonEmpty("A1:B1".to_string())]);
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        spreadsheet.set_value("A1", 4).unwrap();
+        assert_eq!(
+            run_all(&hooks, &spreadsheet, "{}"),
+            Err("B1 must not be empty".to_string())
+        );
+        spreadsheet.set_formula("B1", "A1/0").unwrap();
+        spreadsheet.set_formula("B2", "B1+1").unwrap();
+        assert_eq!(
+            run_all(&hooks, &spreadsheet, "{}"),
+            Err("ERR in B1, B2".to_string())
+        );
+        spreadsheet.set_formula("B1", "A1/2").unwrap();
+        assert_eq!(run_all(&hooks, &spreadsheet, "{}"), Ok(vec![]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_hooks() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempRepo::new("hooks");
+        let mut vcs = VersionControl::new(dir.name(), &2, &2, false).unwrap();
+        let script = |name: &str, body: &str| {
+            let path = dir.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let veto = script(
+            "veto.sh",
+            "if grep -q '\"new_value\":7'; then echo 'no sevens'; exit 1; fi",
+        );
+        let post = script(
+            "post.sh",
+            &format!("cat > {}/post.json; echo posted", dir.display()),
+        );
+        vcs.config.pre_commit = vec![Hook::NoErrors, Hook::Run(veto)];
+        vcs.config.post_commit = vec![Hook::Run(post)];
+
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        spreadsheet.set_value("A1", 7).unwrap();
+        assert_eq!(
+            vcs.commit("seven", &mut spreadsheet),
+            Err(VcsError::HookRejected("no sevens".to_string()))
+        );
+        assert!(vcs.map.is_empty());
+        let options = CommitOptions {
+            message: Some("seven".to_string()),
+            no_verify: true,
+            ..CommitOptions::default()
+        };
+        assert_eq!(
+            vcs.commit_with(&options, &mut spreadsheet),
+            Ok(vec!["posted".to_string()])
+        );
+        let input = fs::read_to_string(dir.join("post.json")).unwrap();
+        assert!(input.contains("\"hook\":\"post-commit\"") && input.contains("\"commit\":1"));
+        assert!(input.contains("\"cell\":\"A1\"") && input.contains("\"message\":\"seven\""));
+
+        spreadsheet.set_formula("B1", "A1/0").unwrap();
+        assert_eq!(
+            vcs.commit("broken", &mut spreadsheet),
+            Err(VcsError::HookRejected("ERR in B1".to_string()))
+        );
+
+        // A failing post-commit hook is reported, but the commit stays
+        vcs.config.pre_commit.clear();
+        vcs.config.post_commit = vec![Hook::Run(script("fail.sh", "echo down; exit 1"))];
+        assert_eq!(
+            vcs.commit("broken", &mut spreadsheet),
+            Ok(vec!["post-commit hook failed: down".to_string()])
+        );
+        assert_eq!(vcs.head(), 2);
+    }
+}
@@ -0,0 +1,106 @@
+use super::error::VcsError;
+
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// Exclusive lock on a repository, held while its files are written and released on drop.
+///
+/// The lock is the file `vcs.lock` holding the owner's process id. A lock left behind by a
+/// process that died while holding it is reclaimed where running processes can be looked
+/// up in `/proc`, i.e. on Linux. Elsewhere every recorded process counts as running and a
+/// stale lock has to be removed by hand.
+pub struct RepoLock {
+    path: String,
+}
+
+impl RepoLock {
+    pub fn acquire(vcs_dir: &str) -> Result<Self, VcsError> {
+        let path = format!("{}/vcs.lock", vcs_dir);
+        if let Some(lock) = Self::create(&path)? {
+            return Ok(lock);
+        }
+        if let Some(pid) = owner(&path).filter(|&pid| !running(pid))
+            && let Some(lock) = Self::take_over(&path, pid)?
+        {
+            return Ok(lock);
+        }
+        let pid = owner(&path);
+        Err(VcsError::Locked(path, pid))
+    }
+
+    /// Replaces the lock left by process `pid`, which is no longer running. Takeovers hold
+    /// `vcs.lock.takeover` and check the owner again, so a process that also saw `pid` cannot
+    /// remove the lock another one has just taken over.
+    fn take_over(path: &str, pid: u32) -> Result<Option<Self>, VcsError> {
+        let Some(_takeover) = Self::create(&format!("{}.takeover", path))? else {
+            return Ok(None);
+        };
+        if owner(path) == Some(pid) {
+            let _ = fs::remove_file(path);
+        }
+        Self::create(path)
+    }
+
+    /// Creates the lock file, or returns `None` if it already exists
+    fn create(path: &str) -> Result<Option<Self>, VcsError> {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                let _ = write!(file, "{}", std::process::id());
+                Ok(Some(RepoLock {
+                    path: path.to_string(),
+                }))
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Process id recorded in the lock file, `None` while the owner has not written it yet
+fn owner(path: &str) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Whether process `pid` is running, assumed to be true where `/proc` is not available
+fn running(pid: u32) -> bool {
+    !Path::new("/proc/self").exists() || Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = TempRepo::new("lock");
+        let dir_name = dir.name();
+        let path = format!("{}/vcs.lock", dir_name);
+        let lock = RepoLock::acquire(&dir_name).unwrap();
+        assert_eq!(
+            RepoLock::acquire(&dir_name).err(),
+            Some(VcsError::Locked(path.clone(), Some(std::process::id())))
+        );
+        drop(lock);
+        assert!(RepoLock::acquire(&dir_name).is_ok());
+
+        // The lock of a process that is no longer running is taken over
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+        let lock = RepoLock::acquire(&dir_name).unwrap();
+        assert_eq!(owner(&path), Some(std::process::id()));
+        assert!(!dir.join("vcs.lock.takeover").exists());
+
+        // A process that saw the same dead owner leaves the new lock alone
+        assert!(RepoLock::take_over(&path, u32::MAX).unwrap().is_none());
+        assert_eq!(owner(&path), Some(std::process::id()));
+        drop(lock);
+    }
+}
@@ -0,0 +1,241 @@
+use super::error::VcsError;
+use super::vcs_engine::{CommitInfo, VersionControl};
+
+use chrono::{DateTime, Local};
+
+/// Options of `gitsap log [--graph] [-n <COUNT>]`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogOptions {
+    /// Draw the parent tree next to the commits
+    pub graph: bool,
+    /// Show at most this many commits
+    pub limit: Option<usize>,
+}
+
+impl LogOptions {
+    pub fn parse(args: &[String]) -> Result<Self, VcsError> {
+        let mut options = LogOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--graph" => options.graph = true,
+                "-n" => {
+                    let count = args
+                        .next()
+                        .and_then(|count| count.parse::<usize>().ok())
+                        .ok_or_else(|| VcsError::InvalidOption(arg.clone()))?;
+                    options.limit = Some(count);
+                }
+                _ => return Err(VcsError::InvalidOption(arg.clone())),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Renders the history newest first, one line per commit.
+///
+/// A commit gets a larger id than its parents, so sorting by id lists children before
+/// their parents. HEAD and branch tips are shown next to their commit.
+pub fn log(vcs: &VersionControl, options: &LogOptions) -> Vec<String> {
+    let mut ids: Vec<usize> = vcs.commits().keys().copied().collect();
+    ids.sort_unstable_by(|a, b| b.cmp(a));
+    ids.truncate(options.limit.unwrap_or(usize::MAX));
+
+    let describe = |id: usize| describe(vcs, id, &vcs.commits()[&id]);
+    if !options.graph {
+        return ids.into_iter().map(describe).collect();
+    }
+
+    let mut lines = vec![];
+    // Commit each column of the graph is waiting for
+    let mut lanes: Vec<Option<usize>> = vec![];
+    for id in ids {
+        let col = match lanes.iter().position(|&lane| lane == Some(id)) {
+            Some(col) => col,
+            None => {
+                let col = lanes
+                    .iter()
+                    .position(|lane| lane.is_none())
+                    .unwrap_or(lanes.len());
+                if col == lanes.len() {
+                    lanes.push(None);
+                }
+                lanes[col] = Some(id);
+                col
+            }
+        };
+
+        // Other children of this commit join its column
+        for joining in (col + 1)..lanes.len() {
+            if lanes[joining] == Some(id) {
+                lanes[joining] = None;
+                lines.push(connector(&lanes, joining, '/'));
+            }
+        }
+
+        lines.push(format!("{} {}", row(&lanes, col), describe(id)));
+
+        let info = &vcs.commits()[&id];
+        lanes[col] = (info.parent != 0).then_some(info.parent);
+        if let Some(merged) = info.merge_parent
+            && !lanes.contains(&Some(merged))
+        {
+            let lane = (col + 1..lanes.len())
+                .find(|&lane| lanes[lane].is_none())
+                .unwrap_or(lanes.len());
+            if lane == lanes.len() {
+                lanes.push(None);
+            }
+            lanes[lane] = Some(merged);
+            lines.push(connector(&lanes, lane, '\\'));
+        }
+        while lanes.last() == Some(&None) {
+            lanes.pop();
+        }
+    }
+    lines
+}
+
+/// `*` for the commit's column and `|` for every other open column
+fn row(lanes: &[Option<usize>], col: usize) -> String {
+    let cells: Vec<&str> = lanes
+        .iter()
+        .enumerate()
+        .map(|(i, lane)| match lane {
+            _ if i == col => "*",
+            Some(_) => "|",
+            None => " ",
+        })
+        .collect();
+    cells.join(" ").trim_end().to_string()
+}
+
+/// A row where column `lane` joins (`/`) or leaves (`\`) the column to its left
+fn connector(lanes: &[Option<usize>], lane: usize, edge: char) -> String {
+    let mut line: Vec<char> = lanes
+        .iter()
+        .flat_map(|lane| [if lane.is_some() { '|' } else { ' ' }, ' '])
+        .collect();
+    line[2 * lane] = ' ';
+    line[2 * lane - 1] = edge;
+    line.iter().collect::<String>().trim_end().to_string()
+}
+
+fn describe(vcs: &VersionControl, id: usize, info: &CommitInfo) -> String {
+    let mut refs = vec![];
+    if id == vcs.head() {
+        match vcs.current_branch() {
+            Some(branch) => refs.push(format!("HEAD -> {}", branch)),
+            None => refs.push("HEAD".to_string()),
+        }
+    }
+    for (branch, &tip) in vcs.branches() {
+        if tip == id && (id != vcs.head() || vcs.current_branch() != Some(branch.as_str())) {
+            refs.push(branch.clone());
+        }
+    }
+    for (tag, &tagged) in vcs.tags() {
+        if tagged == id {
+            refs.push(format!("tag: {}", tag));
+        }
+    }
+
+    let mut line = id.to_string();
+    if !refs.is_empty() {
+        line += &format!(" ({})", refs.join(", "));
+    }
+    line += &format!(" {}", info.message);
+    if let Some(signature) = signature(info) {
+        line += &format!(" - {}", signature);
+    }
+    line
+}
+
+/// Author and local date of a commit, e.g. `alice, 2025-04-01 14:30`
+pub(super) fn signature(info: &CommitInfo) -> Option<String> {
+    let date = info
+        .timestamp
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+        .map(|date| {
+            date.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        });
+    match (&info.author, date) {
+        (Some(author), Some(date)) => Some(format!("{}, {}", author, date)),
+        (Some(author), None) => Some(author.clone()),
+        (None, date) => date,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spreadsheet::SpreadSheet;
+    use crate::vcs::temp_repo::TempRepo;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            LogOptions::parse(&args(&["--graph", "-n", "3"])),
+            Ok(LogOptions {
+                graph: true,
+                limit: Some(3)
+            })
+        );
+        assert_eq!(LogOptions::parse(&[]), Ok(LogOptions::default()));
+        assert_eq!(
+            LogOptions::parse(&args(&["-n"])),
+            Err(VcsError::InvalidOption("-n".to_string()))
+        );
+        assert_eq!(
+            LogOptions::parse(&args(&["--all"])),
+            Err(VcsError::InvalidOption("--all".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_log_graph() {
+        let dir = TempRepo::new("log");
+        let mut vcs = VersionControl::new(dir.name(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        vcs.create_branch("other").unwrap();
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("ours", &mut spreadsheet).unwrap();
+        let mut spreadsheet = vcs.switch("other").unwrap();
+        spreadsheet.set_value("B1", 1).unwrap();
+        vcs.commit("theirs", &mut spreadsheet).unwrap();
+        vcs.switch("main").unwrap();
+        vcs.merge(3).unwrap();
+
+        let options = LogOptions {
+            graph: true,
+            limit: None,
+        };
+        let graph: Vec<String> = log(&vcs, &options)
+            .iter()
+            .map(|line| line.split(" - ").next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            graph,
+            [
+                "* 4 (HEAD -> main) Merge commit 3",
+                "|\\",
+                "| * 3 (other) theirs",
+                "* | 2 ours",
+                "|/",
+                "* 1 base",
+            ]
+        );
+
+        let lines = log(&vcs, &LogOptions::parse(&args(&["-n", "2"])).unwrap());
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("3 (other) theirs - "));
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::BTreeMap;
+
+use super::error::VcsError;
+use super::vcs_engine::{CloneSpreadSheet, SerialCell, VersionControl};
+use crate::cell_ref::CellRef;
+use crate::spreadsheet::SpreadSheet;
+use crate::utils::{Status, Type};
+
+/// What brings another commit's changes onto HEAD
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub(super) enum MergeKind {
+    Merge,
+    Revert,
+    CherryPick,
+}
+
+impl MergeKind {
+    /// Message of the commit that finishes applying commit `id`
+    fn message(self, id: usize) -> String {
+        match self {
+            MergeKind::Merge => format!("Merge commit {}", id),
+            MergeKind::Revert => format!("Revert commit {}", id),
+            MergeKind::CherryPick => format!("Cherry-pick commit {}", id),
+        }
+    }
+
+    pub(super) fn in_progress(self) -> &'static str {
+        match self {
+            MergeKind::Merge => "Merging",
+            MergeKind::Revert => "Reverting",
+            MergeKind::CherryPick => "Cherry-picking",
+        }
+    }
+
+    fn done(self) -> &'static str {
+        match self {
+            MergeKind::Merge => "Merged",
+            MergeKind::Revert => "Reverted",
+            MergeKind::CherryPick => "Cherry-picked",
+        }
+    }
+}
+
+/// A merge, revert or cherry-pick that stopped on cells changed differently on both sides,
+/// saved in `vcs.json` until it is committed or abandoned with `gitsap reset`
+#[derive(Clone, Debug, serde_derive::Serialize, serde_derive::Deserialize)]
+pub(super) struct MergeState {
+    pub(super) kind: MergeKind,
+    pub(super) theirs: usize,
+    /// Conflicting cell -> (ours, theirs)
+    pub(super) conflicts: BTreeMap<CellRef, (SerialCell, SerialCell)>,
+    /// Non-empty cells of the merged sheet as of the last resolved conflict
+    #[serde(default)]
+    sheet: Vec<SerialCell>,
+}
+
+impl VersionControl {
+    /// The sheet of a merge waiting for its conflicts to be resolved, with the conflicts
+    /// resolved so far
+    pub fn merge_spreadsheet(&self) -> Option<SpreadSheet> {
+        let merge = self.merge.as_ref()?;
+        let merged = CloneSpreadSheet::from_cells(self.m, self.n, merge.sheet.clone())?;
+        Some(merged.build(self.m, self.n))
+    }
+
+    /// Merges commit `id` into HEAD.
+    ///
+    /// Cells changed on only one side since the common ancestor are taken from that side.
+    /// If a cell was changed differently on both sides, or the merged formulas form a cycle,
+    /// the merge stops: HEAD's formula is kept, the conflicts are listed and have to be
+    /// resolved with [`resolve`](Self::resolve) before committing. Otherwise a merge commit
+    /// with two parents is created. Returns the merged sheet and the lines to print.
+    pub fn merge(&mut self, id: usize) -> Result<(SpreadSheet, Vec<String>), VcsError> {
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        if !self.map.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        if self.curr_commit == 0 {
+            return Err(VcsError::NoCommits);
+        }
+        let base = self.common_ancestor(self.curr_commit, id);
+        if base == Some(id) {
+            return Ok((
+                self.head_spreadsheet(),
+                vec!["Already up to date".to_string()],
+            ));
+        }
+        if base == Some(self.curr_commit) {
+            let spreadsheet = self.reset(id)?;
+            return Ok((spreadsheet, vec![format!("Fast-forward to commit {}", id)]));
+        }
+
+        let base = match base {
+            Some(base) => self.load_commit(base)?,
+            None => CloneSpreadSheet::new(self.m, self.n),
+        };
+        let theirs = self.load_commit(id)?;
+        self.apply_change(MergeKind::Merge, id, &base, &theirs)
+    }
+
+    /// Applies the change from `base` to `theirs` onto HEAD, cell by cell.
+    ///
+    /// Cells HEAD changed differently from `theirs`, and cells whose merged formula forms a
+    /// cycle, are conflicts: HEAD's formula is kept, the conflicts are listed and have to be
+    /// resolved with [`resolve`](Self::resolve) before committing. Otherwise the result is
+    /// committed right away. Returns the sheet and the lines to print.
+    pub(super) fn apply_change(
+        &mut self,
+        kind: MergeKind,
+        id: usize,
+        base: &CloneSpreadSheet,
+        theirs: &CloneSpreadSheet,
+    ) -> Result<(SpreadSheet, Vec<String>), VcsError> {
+        let mut merged = self.spread_sheet.clone();
+        let mut changed = false;
+        let mut conflicts = BTreeMap::new();
+        for (i, row) in merged.cells.iter_mut().enumerate() {
+            for (j, ours) in row.iter_mut().enumerate() {
+                let (base, theirs) = (&base.cells[i][j], &theirs.cells[i][j]);
+                if theirs.compare(ours) || theirs.compare(base) {
+                    continue;
+                }
+                if ours.compare(base) {
+                    *ours = theirs.clone();
+                    changed = true;
+                } else {
+                    conflicts.insert(CellRef::new(i, j), (ours.clone(), theirs.clone()));
+                }
+            }
+        }
+
+        // A formula taken from one side can form a cycle with one kept from the other, e.g.
+        // ours `A1=B1+1` and theirs `B1=A1+1`. A cell that cannot be set is a conflict too.
+        let mut spreadsheet = SpreadSheet::new(self.m, self.n);
+        for cell in merged.cells.iter().flatten() {
+            if cell.t != Type::Nul && cell.apply_to(&mut spreadsheet) != Ok(Status::Ok) {
+                let (i, j) = (cell.row, cell.col);
+                let ours = self.spread_sheet.cells[i][j].clone();
+                let _ = ours.apply_to(&mut spreadsheet);
+                conflicts.insert(CellRef::new(i, j), (ours, theirs.cells[i][j].clone()));
+            }
+        }
+        let mut lines = vec![];
+        if conflicts.is_empty() {
+            if !changed && kind != MergeKind::Merge {
+                lines.push(format!("Commit {} changes nothing on HEAD", id));
+                return Ok((spreadsheet, lines));
+            }
+            lines = self.transaction(|vcs| {
+                vcs.merge = Some(MergeState {
+                    kind,
+                    theirs: id,
+                    conflicts,
+                    sheet: vec![],
+                });
+                vcs.commit(&kind.message(id), &mut spreadsheet)
+            })?;
+            lines.push(format!("{} commit {}", kind.done(), id));
+        } else {
+            for (cell, (ours, theirs)) in &conflicts {
+                lines.push(format!(
+                    "CONFLICT in {}: ours {}, theirs {}",
+                    cell,
+                    ours.describe(),
+                    theirs.describe()
+                ));
+            }
+            lines.push(
+                "Resolve with `gitsap ours <CELL>` or `gitsap theirs <CELL>`, then `gitsap commit <MSG>`"
+                    .to_string(),
+            );
+            let sheet = CloneSpreadSheet::clone_spread(&mut spreadsheet).non_empty_cells();
+            self.transaction(|vcs| {
+                vcs.merge = Some(MergeState {
+                    kind,
+                    theirs: id,
+                    conflicts,
+                    sheet,
+                });
+                vcs.save()
+            })?;
+        }
+        Ok((spreadsheet, lines))
+    }
+
+    /// Resolves a merge conflict in `cell` (or in all cells) by keeping our or their formula.
+    /// Returns the lines to print, which say when no conflicts are left.
+    pub fn resolve(
+        &mut self,
+        cell: Option<&str>,
+        take_theirs: bool,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        let mut merge = self.merge.clone().ok_or(VcsError::NoMergeInProgress)?;
+        let cells: Vec<CellRef> = match cell {
+            Some(cell) => {
+                let cell = cell
+                    .parse::<CellRef>()
+                    .map_err(|_| VcsError::InvalidCell(cell.to_string()))?;
+                if !merge.conflicts.contains_key(&cell) {
+                    return Err(VcsError::NotInConflict(cell));
+                }
+                vec![cell]
+            }
+            None => merge.conflicts.keys().copied().collect(),
+        };
+        for cell in cells {
+            let (ours, theirs) = &merge.conflicts[&cell];
+            let chosen = if take_theirs { theirs } else { ours };
+            if chosen.apply_to(spreadsheet) != Ok(Status::Ok) {
+                return Err(VcsError::CannotApply(cell));
+            }
+            merge.conflicts.remove(&cell);
+        }
+        merge.sheet = CloneSpreadSheet::clone_spread(spreadsheet).non_empty_cells();
+        let lines = if merge.conflicts.is_empty() {
+            vec![
+                "All conflicts resolved, run `gitsap commit <MSG>` to finish the merge".to_string(),
+            ]
+        } else {
+            vec![]
+        };
+        self.transaction(|vcs| {
+            vcs.merge = Some(merge);
+            vcs.save()
+        })?;
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::SerialVcs;
+
+    #[test]
+    fn test_merge() {
+        let dir = TempRepo::new("merge");
+        let mut vcs = VersionControl::new(dir.name(), &3, &3, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_value("A1", 1).unwrap();
+        spreadsheet.set_value("B1", 1).unwrap();
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        vcs.create_branch("other").unwrap();
+
+        spreadsheet.set_value("A1", 2).unwrap();
+        spreadsheet.set_value("B1", 2).unwrap();
+        vcs.commit("ours", &mut spreadsheet).unwrap();
+
+        let mut spreadsheet = vcs.switch("other").unwrap();
+        spreadsheet.set_formula("A2", "A1*10").unwrap();
+        spreadsheet.set_value("B1", 3).unwrap();
+        vcs.commit("theirs", &mut spreadsheet).unwrap();
+
+        vcs.switch("main").unwrap();
+        assert_eq!(vcs.common_ancestor(2, 3), Some(1));
+        let (mut spreadsheet, lines) = vcs.merge(3).unwrap();
+        assert!(lines[0].starts_with("CONFLICT in B1"));
+        // A1 and A2 merge cleanly, B1 is in conflict and keeps our value
+        assert_eq!(spreadsheet.get_cell_value(1, 0), Ok(Some(20)));
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(2)));
+        assert_eq!(
+            vcs.commit("merge", &mut spreadsheet),
+            Err(VcsError::UnresolvedConflicts(1))
+        );
+        assert_eq!(vcs.switch("other").err(), Some(VcsError::MergeInProgress));
+
+        // The merge survives reopening the repository, with the merged sheet
+        let dir_name = dir.name();
+        let reopened = VersionControl::load(SerialVcs::load(&dir_name).unwrap(), dir_name).unwrap();
+        assert_eq!(reopened.merge.as_ref().unwrap().conflicts.len(), 1);
+        let merged = reopened.merge_spreadsheet().unwrap();
+        assert_eq!(merged.get_cell_value(1, 0), Ok(Some(20)));
+        assert_eq!(
+            vcs.resolve(Some("A1"), true, &mut spreadsheet),
+            Err(VcsError::NotInConflict(CellRef::new(0, 0)))
+        );
+        assert_eq!(
+            vcs.resolve(Some("B1"), true, &mut spreadsheet).unwrap(),
+            ["All conflicts resolved, run `gitsap commit <MSG>` to finish the merge"]
+        );
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(3)));
+        vcs.commit("merge", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.map[&4].parent, 2);
+        assert_eq!(vcs.map[&4].merge_parent, Some(3));
+        assert_eq!(vcs.branches["main"], 4);
+
+        // Merging again is a no-op, and `other` can fast-forward to the merge
+        let (spreadsheet, lines) = vcs.merge(3).unwrap();
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(3)));
+        assert_eq!(lines, ["Already up to date"]);
+        vcs.switch("other").unwrap();
+        let spreadsheet = vcs.merge(4).unwrap().0;
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(2)));
+        assert_eq!(vcs.branches["other"], 4);
+
+        // Formulas that only form a cycle together are a conflict instead of being dropped
+        let mut spreadsheet = vcs.switch("main").unwrap();
+        spreadsheet.set_formula("C1", "C2+1").unwrap();
+        vcs.commit("ours", &mut spreadsheet).unwrap();
+        let mut spreadsheet = vcs.switch("other").unwrap();
+        spreadsheet.set_formula("C2", "C1+1").unwrap();
+        vcs.commit("theirs", &mut spreadsheet).unwrap();
+        vcs.switch("main").unwrap();
+        let mut spreadsheet = vcs.merge(6).unwrap().0;
+        assert_eq!(
+            vcs.commit("merge", &mut spreadsheet),
+            Err(VcsError::UnresolvedConflicts(1))
+        );
+        assert_eq!(
+            vcs.resolve(Some("C2"), true, &mut spreadsheet),
+            Err(VcsError::CannotApply(CellRef::new(1, 2)))
+        );
+        vcs.resolve(Some("C2"), false, &mut spreadsheet).unwrap();
+        vcs.commit("merge", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.map[&7].merge_parent, Some(6));
+    }
+}
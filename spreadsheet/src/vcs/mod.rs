@@ -1 +1,26 @@
+pub mod backend;
+pub mod bisect;
+pub mod blame;
+mod branch;
+pub mod bundle;
+pub mod commit;
+pub mod config;
+pub mod diff;
+pub mod error;
+mod fsck;
+pub mod hooks;
+mod lock;
+pub mod log;
+mod merge;
+mod objects;
+mod remote;
+mod revert;
+mod snapshot;
+mod squash;
+pub mod stash;
+mod tag;
+#[cfg(test)]
+mod temp_repo;
+pub mod values;
 pub mod vcs_engine;
+pub mod vcs_engine_2;
@@ -0,0 +1,120 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+/// Why an object could not be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectError {
+    Missing,
+    /// The content does not match its hash or cannot be parsed
+    Corrupt,
+}
+
+/// Hex encoded SHA-256 of `bytes`
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn object_path(vcs_dir: &str, hash: &str) -> String {
+    format!("{}/objects/{}.json", vcs_dir, hash)
+}
+
+/// Stores `object` as JSON under the hash of its content and returns the hash
+pub fn write_object<T: Serialize>(vcs_dir: &str, object: &T) -> io::Result<String> {
+    write_bytes(vcs_dir, &serde_json::to_vec(object)?)
+}
+
+/// Stores `bytes` under their hash and returns the hash
+pub fn write_bytes(vcs_dir: &str, bytes: &[u8]) -> io::Result<String> {
+    let hash = hash(bytes);
+    fs::create_dir_all(format!("{}/objects", vcs_dir))?;
+    write_atomic(&object_path(vcs_dir, &hash), bytes)?;
+    Ok(hash)
+}
+
+/// Deletes the object stored under `hash`, returning whether there was one
+pub fn remove(vcs_dir: &str, hash: &str) -> bool {
+    fs::remove_file(object_path(vcs_dir, hash)).is_ok()
+}
+
+/// Hashes of all stored objects
+pub fn list(vcs_dir: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(format!("{}/objects", vcs_dir)) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.strip_suffix(".json").map(str::to_string)
+        })
+        .collect()
+}
+
+/// Writes `bytes` to `path` through a temporary file that replaces it in one rename, so a
+/// crash leaves either the old or the new content
+pub fn write_atomic(path: &str, bytes: &[u8]) -> io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+/// Deletes the temporary files of writes that were interrupted
+pub fn remove_temp_files(vcs_dir: &str) {
+    for dir in [vcs_dir.to_string(), format!("{}/objects", vcs_dir)] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().ends_with(".tmp") {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Reads the object stored under `hash`, checking that its content still has that hash
+pub fn read_object<T: DeserializeOwned>(vcs_dir: &str, hash: &str) -> Result<T, ObjectError> {
+    serde_json::from_slice(&read_bytes(vcs_dir, hash)?).map_err(|_| ObjectError::Corrupt)
+}
+
+/// The content stored under `hash`, checked against the hash
+pub fn read_bytes(vcs_dir: &str, hash: &str) -> Result<Vec<u8>, ObjectError> {
+    let bytes = fs::read(object_path(vcs_dir, hash)).map_err(|_| ObjectError::Missing)?;
+    if self::hash(&bytes) != hash {
+        return Err(ObjectError::Corrupt);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_write_and_read_object() {
+        let temp = TempRepo::new("objects");
+        let dir = temp.name();
+        let hash = write_object(&dir, &vec![1, 2, 3]).unwrap();
+        assert_eq!(hash, self::hash(b"[1,2,3]"));
+        assert_eq!(read_object::<Vec<i32>>(&dir, &hash), Ok(vec![1, 2, 3]));
+
+        fs::write(object_path(&dir, &hash), "[1,2,4]").unwrap();
+        assert_eq!(
+            read_object::<Vec<i32>>(&dir, &hash),
+            Err(ObjectError::Corrupt)
+        );
+        assert_eq!(
+            read_object::<Vec<i32>>(&dir, "0000"),
+            Err(ObjectError::Missing)
+        );
+    }
+}
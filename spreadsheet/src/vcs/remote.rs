@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use super::error::VcsError;
+use super::objects::{self, ObjectError};
+use super::vcs_engine::{CommitInfo, SerialVcs, VersionControl, valid_name};
+use crate::spreadsheet::SpreadSheet;
+
+impl VersionControl {
+    /// Remembers the repository in directory `path` as remote `name`
+    pub fn add_remote(&mut self, name: &str, path: &str) -> Result<(), VcsError> {
+        if !valid_name(name) {
+            return Err(VcsError::InvalidRemoteName(name.to_string()));
+        }
+        if self.remotes.contains_key(name) {
+            return Err(VcsError::RemoteExists(name.to_string()));
+        }
+        self.transaction(|vcs| {
+            vcs.remotes.insert(name.to_string(), path.to_string());
+            vcs.save()
+        })
+    }
+
+    pub fn remove_remote(&mut self, name: &str) -> Result<(), VcsError> {
+        if !self.remotes.contains_key(name) {
+            return Err(VcsError::UnknownRemote(name.to_string()));
+        }
+        self.transaction(|vcs| {
+            vcs.remotes.remove(name);
+            vcs.save()
+        })
+    }
+
+    /// All remotes with their directories
+    pub fn list_remotes(&self) -> Vec<String> {
+        self.remotes
+            .iter()
+            .map(|(name, path)| format!("{} {}", name, path))
+            .collect()
+    }
+
+    /// The remote called `name`, or the only remote if no name is given
+    fn remote(&self, name: Option<&str>) -> Result<(String, String), VcsError> {
+        let (name, path) = match name {
+            Some(name) => self
+                .remotes
+                .get_key_value(name)
+                .ok_or_else(|| VcsError::UnknownRemote(name.to_string()))?,
+            None if self.remotes.len() == 1 => self.remotes.iter().next().unwrap(),
+            None => return Err(VcsError::NoDefaultRemote),
+        };
+        Ok((name.clone(), path.clone()))
+    }
+
+    /// Sends the current branch to a remote. The branch is created there, or fast-forwarded
+    /// if its tip there is a commit of ours; it is never moved off commits we do not have.
+    /// Pushing to a directory without a repository creates one. Returns the line to print.
+    pub fn push(&mut self, remote: Option<&str>) -> Result<String, VcsError> {
+        let (name, path) = self.remote(remote)?;
+        let branch = self.current_branch.clone().ok_or(VcsError::DetachedHead)?;
+        let &tip = self.branches.get(&branch).ok_or(VcsError::NoCommits)?;
+        let mut other = match SerialVcs::load(&path) {
+            Ok(serial_vcs) => VersionControl::load(serial_vcs, path)?,
+            Err(VcsError::MissingIndex(_)) => VersionControl::new(path, &self.m, &self.n, false)?,
+            Err(err) => return Err(err),
+        };
+        let _lock = other.lock()?;
+        if let Some(&other_tip) = other.branches.get(&branch) {
+            let other_hash = other.object_hash(other_tip);
+            if other_hash.is_some() && other_hash == self.object_hash(tip) {
+                return Ok("Everything up to date".to_string());
+            }
+            let behind = other_hash.is_some()
+                && self
+                    .ancestors(tip)
+                    .iter()
+                    .any(|&id| self.object_hash(id) == other_hash);
+            if !behind {
+                return Err(VcsError::PushRejected(branch));
+            }
+        }
+        let other_tip = other.import(self, tip)?;
+        other.branches.insert(branch.clone(), other_tip);
+        if other.current_branch.as_ref() == Some(&branch) {
+            other.curr_commit = other_tip;
+        }
+        other.write_index()?;
+        Ok(format!("Pushed `{}` to `{}`", branch, name))
+    }
+
+    /// Fetches the current branch from a remote and fast-forwards to it. If both sides have
+    /// new commits, they are fetched without moving HEAD and the remote tip is reported so
+    /// it can be merged. Returns the sheet at HEAD and the lines to print.
+    pub fn pull(&mut self, remote: Option<&str>) -> Result<(SpreadSheet, Vec<String>), VcsError> {
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        let (name, path) = self.remote(remote)?;
+        let branch = self.current_branch.clone().ok_or(VcsError::DetachedHead)?;
+        let other = VersionControl::load(SerialVcs::load(&path)?, path)?;
+        let &other_tip = other
+            .branches
+            .get(&branch)
+            .ok_or_else(|| VcsError::UnknownBranch(branch.clone()))?;
+        let tip = {
+            let _lock = self.lock()?;
+            self.transaction(|vcs| {
+                let tip = vcs.import(&other, other_tip)?;
+                vcs.write_index()?;
+                Ok(tip)
+            })?
+        };
+        let head = self.curr_commit;
+        if head != 0 && self.ancestors(head).contains(&tip) {
+            return Ok((
+                self.head_spreadsheet(),
+                vec!["Already up to date".to_string()],
+            ));
+        }
+        if head == 0 || self.ancestors(tip).contains(&head) {
+            let spreadsheet = self.reset(tip)?;
+            return Ok((spreadsheet, vec![format!("Fast-forward to commit {}", tip)]));
+        }
+        let line = format!(
+            "`{}` has diverged from `{}`, run `gitsap merge {}` to combine them",
+            branch, name, tip
+        );
+        Ok((self.head_spreadsheet(), vec![line]))
+    }
+
+    /// Copies commit `tip` of `source` and those of its ancestors this repository lacks, and
+    /// returns the id `tip` has here. Ids differ between repositories, so commits are matched
+    /// by hash and copies get new ids. The repository lock has to be held.
+    fn import(&mut self, source: &VersionControl, tip: usize) -> Result<usize, VcsError> {
+        if (source.m, source.n) != (self.m, self.n) {
+            return Err(VcsError::SizeMismatch);
+        }
+        let mut known: HashMap<String, usize> = self
+            .map
+            .iter()
+            .filter_map(|(&id, info)| Some((info.hash.clone()?, id)))
+            .collect();
+        let mut missing: Vec<usize> = source
+            .ancestors(tip)
+            .into_iter()
+            .filter(|&id| {
+                source
+                    .object_hash(id)
+                    .is_none_or(|hash| !known.contains_key(&hash))
+            })
+            .collect();
+        // parents have smaller ids, so they are copied first
+        missing.sort_unstable();
+        for id in missing {
+            let info = &source.map[&id];
+            let hash = info.hash.clone().ok_or(VcsError::UnhashedCommit(id))?;
+            let bytes = objects::read_bytes(&source.vcs_dir, &hash).map_err(|err| match err {
+                ObjectError::Missing => VcsError::MissingCommit(id),
+                ObjectError::Corrupt => VcsError::CorruptCommit(id),
+            })?;
+            objects::write_bytes(&self.vcs_dir, &bytes)?;
+            // a parent that was not copied before its child means the source is corrupt
+            let local = |parent: usize| match source.object_hash(parent) {
+                Some(hash) => known.get(&hash).copied().ok_or(VcsError::CorruptCommit(id)),
+                None => Ok(0),
+            };
+            let copy = CommitInfo {
+                parent: local(info.parent)?,
+                message: info.message.clone(),
+                merge_parent: info.merge_parent.map(local).transpose()?,
+                author: info.author.clone(),
+                timestamp: info.timestamp,
+                snapshot: false,
+                hash: Some(hash.clone()),
+                snapshot_hash: None,
+            };
+            let local_id = self.next_commit;
+            self.next_commit += 1;
+            self.map.insert(local_id, copy);
+            known.insert(hash, local_id);
+            // snapshots record their commit id, so they are rebuilt rather than copied
+            if self.needs_snapshot(local_id) {
+                let spread_sheet = self.load_commit(local_id)?;
+                self.write_snapshot(local_id, &spread_sheet)?;
+            }
+        }
+        let hash = source
+            .object_hash(tip)
+            .ok_or(VcsError::UnhashedCommit(tip))?;
+        known
+            .get(&hash)
+            .copied()
+            .ok_or(VcsError::CorruptCommit(tip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_push_and_pull() {
+        let dir = TempRepo::new("remote");
+        let mut alice = VersionControl::new(dir.file("alice"), &2, &2, false).unwrap();
+        let mut bob = VersionControl::new(dir.file("bob"), &2, &2, false).unwrap();
+        alice.add_remote("origin", &dir.file("shared")).unwrap();
+        bob.add_remote("origin", &dir.file("shared")).unwrap();
+        assert_eq!(
+            alice.add_remote("origin", "elsewhere"),
+            Err(VcsError::RemoteExists("origin".to_string()))
+        );
+
+        // Pushing to an empty folder creates the central repository
+        let mut sheet_a = SpreadSheet::new(2, 2);
+        sheet_a.set_value("A1", 1).unwrap();
+        alice.commit("one", &mut sheet_a).unwrap();
+        assert_eq!(alice.push(None).unwrap(), "Pushed `main` to `origin`");
+        let (mut sheet_b, _) = bob.pull(Some("origin")).unwrap();
+        assert_eq!(bob.head(), 1);
+        assert_eq!(sheet_b.get_cell_value(0, 0), Ok(Some(1)));
+        assert_eq!(bob.object_hash(1), alice.object_hash(1));
+
+        // Fast-forward in both directions
+        sheet_b.set_value("A2", 2).unwrap();
+        bob.commit("two", &mut sheet_b).unwrap();
+        bob.push(None).unwrap();
+        assert_eq!(bob.push(None).unwrap(), "Everything up to date");
+        let lines;
+        (sheet_a, lines) = alice.pull(None).unwrap();
+        assert_eq!(lines, ["Fast-forward to commit 2"]);
+        assert_eq!(sheet_a.get_cell_value(1, 0), Ok(Some(2)));
+
+        // Diverged branches are refused on push and merged after pulling
+        sheet_a.set_value("B1", 3).unwrap();
+        alice.commit("alice", &mut sheet_a).unwrap();
+        sheet_b.set_value("B2", 4).unwrap();
+        bob.commit("bob", &mut sheet_b).unwrap();
+        bob.push(None).unwrap();
+        assert_eq!(
+            alice.push(None),
+            Err(VcsError::PushRejected("main".to_string()))
+        );
+        let (sheet, lines) = alice.pull(None).unwrap();
+        assert_eq!(sheet.get_cell_value(1, 1), Ok(Some(0)));
+        assert!(lines[0].contains("has diverged"));
+        let fetched = alice.next_commit - 1;
+        assert_eq!(alice.map[&fetched].message, "bob");
+        sheet_a = alice.merge(fetched).unwrap().0;
+        assert_eq!(sheet_a.get_cell_value(1, 1), Ok(Some(4)));
+        alice.push(None).unwrap();
+        sheet_b = bob.pull(None).unwrap().0;
+        assert_eq!(sheet_b.get_cell_value(0, 1), Ok(Some(3)));
+        assert_eq!(bob.fsck(), vec![]);
+
+        let mut other = VersionControl::new(dir.file("other"), &3, &3, false).unwrap();
+        other.add_remote("origin", &dir.file("shared")).unwrap();
+        other.current_branch = Some("main".to_string());
+        assert_eq!(other.pull(None).err(), Some(VcsError::SizeMismatch));
+    }
+}
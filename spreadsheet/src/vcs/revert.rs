@@ -0,0 +1,102 @@
+use super::error::VcsError;
+use super::merge::MergeKind;
+use super::vcs_engine::{CloneSpreadSheet, VersionControl};
+use crate::spreadsheet::SpreadSheet;
+
+impl VersionControl {
+    /// Creates a commit undoing the changes commit `id` made to its first parent.
+    ///
+    /// Cells changed again since `id` conflict and are resolved like merge conflicts.
+    pub fn revert(&mut self, id: usize) -> Result<(SpreadSheet, Vec<String>), VcsError> {
+        let (parent, commit) = self.commit_change(id)?;
+        self.apply_change(MergeKind::Revert, id, &commit, &parent)
+    }
+
+    /// Creates a commit repeating the changes commit `id` made to its first parent on top of
+    /// HEAD.
+    ///
+    /// Cells HEAD changed differently conflict and are resolved like merge conflicts.
+    pub fn cherry_pick(&mut self, id: usize) -> Result<(SpreadSheet, Vec<String>), VcsError> {
+        let (parent, commit) = self.commit_change(id)?;
+        self.apply_change(MergeKind::CherryPick, id, &parent, &commit)
+    }
+
+    /// The sheets before and after commit `id`, for reverting or cherry-picking it
+    fn commit_change(&self, id: usize) -> Result<(CloneSpreadSheet, CloneSpreadSheet), VcsError> {
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        let info = self.map.get(&id).ok_or(VcsError::UnknownCommit(id))?;
+        if self.curr_commit == 0 {
+            return Err(VcsError::NoCommits);
+        }
+        let parent = match info.parent {
+            0 => CloneSpreadSheet::new(self.m, self.n),
+            parent => self.load_commit(parent)?,
+        };
+        Ok((parent, self.load_commit(id)?))
+    }
+
+    /// Moves HEAD, and the current branch with it, to commit `id`. Later commits stay in the
+    /// repository and can still be checked out by id. Abandons a merge in progress.
+    pub fn reset(&mut self, id: usize) -> Result<SpreadSheet, VcsError> {
+        if !self.map.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        self.transaction(|vcs| {
+            let branch = vcs.current_branch.clone();
+            if let Some(branch) = &branch {
+                vcs.branches.insert(branch.clone(), id);
+            }
+            vcs.merge = None;
+            vcs.move_head(id, branch)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_revert_reset_and_cherry_pick() {
+        let dir = TempRepo::new("revert");
+        let mut vcs = VersionControl::new(dir.name(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("B1", 5).unwrap();
+        vcs.commit("bad", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 2).unwrap();
+        vcs.commit("later", &mut spreadsheet).unwrap();
+
+        // Undoing commit 2 keeps the later change to A1
+        let spreadsheet = vcs.revert(2).unwrap().0;
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(0)));
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(2)));
+        assert_eq!(vcs.map[&4].message, "Revert commit 2");
+        assert_eq!(vcs.map[&4].merge_parent, None);
+        assert_eq!(vcs.revert(2).unwrap().0.get_cell_value(0, 1), Ok(Some(0)));
+        assert_eq!(vcs.head(), 4);
+
+        // Commit 2 set B1 over an empty cell, so re-applying it conflicts with nothing
+        let spreadsheet = vcs.cherry_pick(2).unwrap().0;
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(5)));
+        assert_eq!(vcs.map[&5].parent, 4);
+
+        // Commit 3 changed A1 from 1, which HEAD no longer has after a reset to commit 1
+        let mut spreadsheet = vcs.reset(1).unwrap();
+        assert_eq!(vcs.branches()["main"], 1);
+        assert_eq!(vcs.current_branch(), Some("main"));
+        spreadsheet.set_value("A1", 7).unwrap();
+        vcs.commit("diverge", &mut spreadsheet).unwrap();
+        let mut spreadsheet = vcs.cherry_pick(3).unwrap().0;
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(7)));
+        assert_eq!(vcs.revert(3).err(), Some(VcsError::MergeInProgress));
+        vcs.resolve(Some("A1"), true, &mut spreadsheet).unwrap();
+        vcs.commit("picked", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.map[&7].merge_parent, None);
+        assert_eq!(vcs.head_spreadsheet().get_cell_value(0, 0), Ok(Some(2)));
+    }
+}
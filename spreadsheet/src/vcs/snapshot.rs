@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::fs;
+
+use super::error::VcsError;
+use super::objects;
+use super::vcs_engine::{CloneSpreadSheet, SerialCell, SerialSheetDiff, VersionControl};
+
+impl VersionControl {
+    /// Non-empty cells of the snapshot of commit `id`, checked against its hash
+    pub(super) fn read_snapshot(&self, id: usize) -> Result<Vec<SerialCell>, VcsError> {
+        let info = self.map.get(&id).ok_or(VcsError::UnknownCommit(id))?;
+        let hash = info
+            .snapshot_hash
+            .as_ref()
+            .ok_or(VcsError::CorruptSnapshot(id))?;
+        let snapshot: SerialSheetDiff =
+            objects::read_object(&self.vcs_dir, hash).map_err(|_| VcsError::CorruptSnapshot(id))?;
+        if snapshot.id != id || !self.in_bounds(&snapshot.cells) {
+            return Err(VcsError::CorruptSnapshot(id));
+        }
+        Ok(snapshot.cells)
+    }
+
+    /// The nearest first-parent ancestor of `id` (itself included) with a snapshot, and the
+    /// commits after it whose diffs rebuild `id`, oldest first
+    pub(super) fn diffs_since_snapshot(
+        &self,
+        id: usize,
+    ) -> Result<(Option<usize>, Vec<usize>), VcsError> {
+        let mut commit_chain = vec![];
+        let mut commit = id;
+        while commit != 0 {
+            let info = self
+                .map
+                .get(&commit)
+                .ok_or(VcsError::UnknownCommit(commit))?;
+            if info.snapshot {
+                commit_chain.reverse();
+                return Ok((Some(commit), commit_chain));
+            }
+            commit_chain.push(commit);
+            commit = info.parent;
+        }
+        commit_chain.reverse();
+        Ok((None, commit_chain))
+    }
+
+    /// Whether rebuilding `id` would replay at least `snapshot_interval` diffs
+    pub(super) fn needs_snapshot(&self, id: usize) -> bool {
+        let interval = self.config.snapshot_interval();
+        interval > 0
+            && self
+                .diffs_since_snapshot(id)
+                .is_ok_and(|(_, diffs)| diffs.len() >= interval)
+    }
+
+    /// Stores every non-empty cell of commit `id`
+    pub(super) fn write_snapshot(
+        &mut self,
+        id: usize,
+        spread_sheet: &CloneSpreadSheet,
+    ) -> Result<(), VcsError> {
+        let snapshot = SerialSheetDiff {
+            id,
+            cells: spread_sheet.non_empty_cells(),
+        };
+        let hash = objects::write_object(&self.vcs_dir, &snapshot)?;
+        if let Some(info) = self.map.get_mut(&id) {
+            info.snapshot = true;
+            info.snapshot_hash = Some(hash);
+        }
+        Ok(())
+    }
+
+    /// Writes snapshots for commits that are too far from one, then deletes the files no
+    /// commit needs: the legacy `commit_<id>.json` diffs of snapshotted commits, which are
+    /// never replayed, and objects nothing refers to, such as those of popped stashes or of
+    /// commits whose index write failed. Commit objects in use are kept since later commits'
+    /// hashes depend on them. Returns the number of snapshots written and files deleted.
+    pub fn gc(&mut self) -> Result<(usize, usize), VcsError> {
+        let _lock = self.lock()?;
+        let mut ids: Vec<usize> = self.map.keys().copied().collect();
+        ids.sort_unstable();
+
+        let written = self.transaction(|vcs| {
+            let mut written = 0;
+            for &id in &ids {
+                if !vcs.map[&id].snapshot && vcs.needs_snapshot(id) {
+                    let spread_sheet = vcs.load_commit(id)?;
+                    vcs.write_snapshot(id, &spread_sheet)?;
+                    written += 1;
+                }
+            }
+            vcs.write_index()?;
+            Ok(written)
+        })?;
+
+        let mut removed = 0;
+        for &id in &ids {
+            let info = &self.map[&id];
+            if info.snapshot && info.hash.is_none() && fs::remove_file(self.commit_path(id)).is_ok()
+            {
+                removed += 1;
+            }
+        }
+        let used = self.used_objects();
+        for hash in objects::list(&self.vcs_dir) {
+            if !used.contains(&hash) && objects::remove(&self.vcs_dir, &hash) {
+                removed += 1;
+            }
+        }
+        Ok((written, removed))
+    }
+
+    /// Hashes of the objects commits and stashes refer to
+    pub(super) fn used_objects(&self) -> HashSet<String> {
+        self.map
+            .values()
+            .flat_map(|info| info.hash.iter().chain(info.snapshot_hash.iter()))
+            .chain(self.stashes.iter().map(|stash| &stash.hash))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spreadsheet::SpreadSheet;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_snapshots_and_gc() {
+        let dir = TempRepo::new("gc");
+        let mut vcs = VersionControl::new(dir.name(), &2, &2, false).unwrap();
+        vcs.config.snapshot_interval = Some(0);
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        for value in 1..=5 {
+            spreadsheet.set_value("A1", value).unwrap();
+            spreadsheet.set_formula("B1", "A1*2").unwrap();
+            vcs.commit("commit", &mut spreadsheet).unwrap();
+        }
+        assert!(vcs.map.values().all(|info| !info.snapshot));
+
+        vcs.config.snapshot_interval = Some(2);
+        spreadsheet.set_value("A1", 6).unwrap();
+        vcs.commit("snapshot", &mut spreadsheet).unwrap();
+        assert!(vcs.map[&6].snapshot);
+        assert_eq!(vcs.diffs_since_snapshot(6), Ok((Some(6), vec![])));
+
+        // An object nothing refers to, as left by a failed commit
+        let unused = objects::write_object(&vcs.vcs_dir, &"unused").unwrap();
+        assert_eq!(vcs.gc(), Ok((2, 1)));
+        assert!(
+            !dir.join("objects")
+                .join(format!("{}.json", unused))
+                .exists()
+        );
+        assert!(vcs.map[&2].snapshot && vcs.map[&4].snapshot);
+        assert_eq!(vcs.diffs_since_snapshot(5), Ok((Some(4), vec![5])));
+        for id in 1..=6 {
+            let spreadsheet = vcs.checkout(id).unwrap();
+            assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(2 * id as i32)));
+        }
+    }
+}
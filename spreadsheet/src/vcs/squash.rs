@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::commit::CommitOptions;
+use super::error::VcsError;
+use super::objects::{self, ObjectError};
+use super::vcs_engine::{CloneSpreadSheet, CommitObject, VersionControl};
+use crate::spreadsheet::SpreadSheet;
+
+impl VersionControl {
+    /// Replaces the linear run of commits from `from` to `to` by a single commit with the
+    /// changes and messages of all of them, which keeps the id, author and time of `to`.
+    /// Commits built on `to` are rewritten since their hashes cover its hash, but every
+    /// remaining commit rebuilds the same sheet as before. The other commits of the run are
+    /// deleted, so none of them may be a merge, have a child outside the run, or be HEAD or
+    /// named by a branch, tag or stash.
+    pub fn squash(&mut self, from: usize, to: usize) -> Result<(), VcsError> {
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        for id in [from, to] {
+            if !self.map.contains_key(&id) {
+                return Err(VcsError::UnknownCommit(id));
+            }
+        }
+        if from == to {
+            return Err(VcsError::CannotSquash(
+                "give two different commits".to_string(),
+            ));
+        }
+        let mut run = vec![to];
+        let mut commit = to;
+        while commit != from {
+            commit = self.map[&commit].parent;
+            if commit == 0 {
+                return Err(VcsError::CannotSquash(format!(
+                    "commit {} is not a first-parent ancestor of commit {}",
+                    from, to
+                )));
+            }
+            run.push(commit);
+        }
+        // oldest first
+        run.reverse();
+        for &id in &run {
+            let info = &self.map[&id];
+            if info.hash.is_none() {
+                return Err(VcsError::UnhashedCommit(id));
+            }
+            if info.merge_parent.is_some() {
+                return Err(VcsError::CannotSquash(format!("commit {} is a merge", id)));
+            }
+        }
+        for pair in run.windows(2) {
+            if let Some(child) = self.children(pair[0]).into_iter().find(|&c| c != pair[1]) {
+                return Err(VcsError::CannotSquash(format!(
+                    "commit {} is also the parent of commit {}",
+                    pair[0], child
+                )));
+            }
+            if let Some(name) = self.named_by(pair[0]) {
+                return Err(VcsError::CannotSquash(format!(
+                    "{} points to commit {}",
+                    name, pair[0]
+                )));
+            }
+        }
+
+        let lock = self.lock()?;
+        let parent = self.map[&from].parent;
+        let base = match parent {
+            0 => CloneSpreadSheet::new(self.m, self.n),
+            parent => self.load_commit(parent)?,
+        };
+        let cells = self.get_diff_spread(&mut self.load_commit(to)?, &base);
+        let message = run
+            .iter()
+            .map(|id| self.map[id].message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        let old = self
+            .read_commit_object(to)?
+            .ok_or(VcsError::UnhashedCommit(to))?;
+        let object = CommitObject {
+            parent: self.object_hash(parent),
+            merge_parent: None,
+            message: message.clone(),
+            cells,
+            ..old
+        };
+        let hash = objects::write_object(&self.vcs_dir, &object)?;
+
+        let garbage = self.transaction(|vcs| {
+            let mut garbage = vec![];
+            for &id in &run[..run.len() - 1] {
+                garbage.extend(vcs.drop_commit(id));
+            }
+            if let Some(info) = vcs.map.get_mut(&to) {
+                info.parent = parent;
+                info.message = message;
+                garbage.extend(info.hash.replace(hash));
+            }
+            garbage.extend(vcs.rewrite_descendants(to)?);
+            vcs.write_index()?;
+            Ok(garbage)
+        })?;
+        drop(lock);
+        self.remove_objects(garbage);
+        Ok(())
+    }
+
+    /// Replaces HEAD by a new commit with HEAD's parents, keeping HEAD's message and author
+    /// unless new ones are given. Then deletes the replaced commit unless a child, branch, tag
+    /// or stash still uses it. Returns the lines to print, which say if it was kept.
+    pub fn amend(
+        &mut self,
+        options: &CommitOptions,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        let replaced = self.curr_commit;
+        let options = CommitOptions {
+            amend: true,
+            ..options.clone()
+        };
+        let mut lines = self.write_commit(&options, spreadsheet)?;
+        if !self.children(replaced).is_empty() || self.named_by(replaced).is_some() {
+            lines.push(format!("Commit {} is still in use and was kept", replaced));
+            return Ok(lines);
+        }
+        let lock = self.lock()?;
+        let garbage = self.transaction(|vcs| {
+            let garbage = vcs.drop_commit(replaced);
+            vcs.write_index()?;
+            Ok(garbage)
+        })?;
+        drop(lock);
+        self.remove_objects(garbage);
+        Ok(lines)
+    }
+
+    /// Commits whose first or merge parent is `id`
+    fn children(&self, id: usize) -> Vec<usize> {
+        let mut children: Vec<usize> = self
+            .map
+            .keys()
+            .copied()
+            .filter(|&child| self.parents(child).contains(&id))
+            .collect();
+        children.sort_unstable();
+        children
+    }
+
+    /// What refers to commit `id` by id: HEAD, a branch, a tag, a stash made on it or a bisect
+    fn named_by(&self, id: usize) -> Option<String> {
+        if self.curr_commit == id {
+            return Some("HEAD".to_string());
+        }
+        let named = |names: &BTreeMap<String, usize>| {
+            names
+                .iter()
+                .find(|&(_, &commit)| commit == id)
+                .map(|(name, _)| name.clone())
+        };
+        named(&self.branches)
+            .map(|name| format!("branch `{}`", name))
+            .or_else(|| named(&self.tags).map(|name| format!("tag `{}`", name)))
+            .or_else(|| {
+                self.stashes
+                    .iter()
+                    .any(|stash| stash.base == id)
+                    .then(|| "a stash".to_string())
+            })
+            .or_else(|| {
+                self.bisect
+                    .as_ref()
+                    .filter(|bisect| {
+                        bisect.start == id || bisect.bad == Some(id) || bisect.good.contains(&id)
+                    })
+                    .map(|_| "the bisect in progress".to_string())
+            })
+    }
+
+    /// Removes commit `id` from the map and returns the hashes of its objects
+    fn drop_commit(&mut self, id: usize) -> Vec<String> {
+        self.map
+            .remove(&id)
+            .map(|info| info.hash.into_iter().chain(info.snapshot_hash).collect())
+            .unwrap_or_default()
+    }
+
+    /// Writes new objects for the commits built on `id` after its object changed, so their
+    /// parent hashes match again, and returns the hashes of the objects they replace. Legacy
+    /// commits do not record their parents' hashes and end the rewrite.
+    fn rewrite_descendants(&mut self, id: usize) -> Result<Vec<String>, VcsError> {
+        // parents have smaller ids, so they are rewritten first
+        let mut later: Vec<usize> = self.map.keys().copied().filter(|&c| c > id).collect();
+        later.sort_unstable();
+        let mut rewritten = HashSet::from([id]);
+        let mut replaced = vec![];
+        for commit in later {
+            let info = &self.map[&commit];
+            let Some(hash) = info.hash.clone() else {
+                continue;
+            };
+            if !self
+                .parents(commit)
+                .iter()
+                .any(|parent| rewritten.contains(parent))
+            {
+                continue;
+            }
+            let mut object: CommitObject =
+                objects::read_object(&self.vcs_dir, &hash).map_err(|err| match err {
+                    ObjectError::Missing => VcsError::MissingCommit(commit),
+                    ObjectError::Corrupt => VcsError::CorruptCommit(commit),
+                })?;
+            object.parent = self.object_hash(info.parent);
+            object.merge_parent = info.merge_parent.and_then(|p| self.object_hash(p));
+            let new_hash = objects::write_object(&self.vcs_dir, &object)?;
+            if let Some(info) = self.map.get_mut(&commit) {
+                info.hash = Some(new_hash);
+            }
+            replaced.push(hash);
+            rewritten.insert(commit);
+        }
+        Ok(replaced)
+    }
+
+    /// Deletes the objects among `hashes` that no commit or stash uses any more
+    fn remove_objects(&self, hashes: Vec<String>) {
+        let used = self.used_objects();
+        for hash in hashes {
+            if !used.contains(&hash) {
+                objects::remove(&self.vcs_dir, &hash);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::SerialVcs;
+    use std::fs;
+
+    #[test]
+    fn test_squash_and_amend() {
+        let dir = TempRepo::new("squash");
+        let vcs_dir = dir.name();
+        let mut vcs = VersionControl::new(vcs_dir.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("set A1", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 2).unwrap();
+        spreadsheet.set_formula("B1", "A1*2").unwrap();
+        vcs.commit("double A1", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A2", 5).unwrap();
+        vcs.commit("set A2", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A2", 6).unwrap();
+        vcs.commit("fix A2", &mut spreadsheet).unwrap();
+
+        vcs.create_tag("v1", 3).unwrap();
+        assert_eq!(
+            vcs.squash(2, 4),
+            Err(VcsError::CannotSquash(
+                "tag `v1` points to commit 3".to_string()
+            ))
+        );
+        vcs.delete_tag("v1").unwrap();
+        assert!(matches!(vcs.squash(5, 1), Err(VcsError::CannotSquash(_))));
+        vcs.squash(2, 4).unwrap();
+        assert!(!vcs.map.contains_key(&2) && !vcs.map.contains_key(&3));
+        assert_eq!(vcs.map[&4].parent, 1);
+        assert_eq!(vcs.map[&4].message, "set A1; double A1; set A2");
+        assert_eq!(vcs.map[&5].parent, 4);
+        assert_eq!(vcs.fsck(), vec![]);
+        let squashed = vcs.spreadsheet_at(4).unwrap();
+        assert_eq!(squashed.get_cell_value(0, 1), Ok(Some(4)));
+        assert_eq!(squashed.get_cell_value(1, 0), Ok(Some(5)));
+        // Only the objects of the remaining commits are left
+        let objects = fs::read_dir(dir.join("objects")).unwrap().count();
+        assert_eq!(objects, vcs.map.len());
+
+        spreadsheet.set_value("A2", 7).unwrap();
+        let options = CommitOptions::default();
+        assert_eq!(vcs.amend(&options, &mut spreadsheet), Ok(vec![]));
+        assert_eq!(vcs.head(), 6);
+        assert!(!vcs.map.contains_key(&5));
+        assert_eq!(vcs.map[&6].message, "fix A2");
+        vcs.create_branch("keep").unwrap();
+        assert_eq!(
+            vcs.amend(&options, &mut spreadsheet),
+            Ok(vec!["Commit 6 is still in use and was kept".to_string()])
+        );
+        assert!(vcs.map.contains_key(&6));
+
+        let vcs = VersionControl::load(SerialVcs::load(&vcs_dir).unwrap(), vcs_dir).unwrap();
+        assert_eq!(vcs.head(), 7);
+        assert_eq!(vcs.head_spreadsheet().get_cell_value(1, 0), Ok(Some(7)));
+        assert_eq!(vcs.fsck(), vec![]);
+    }
+}
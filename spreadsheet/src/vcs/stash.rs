@@ -0,0 +1,152 @@
+use super::error::VcsError;
+use super::objects;
+use super::vcs_engine::{CloneSpreadSheet, SerialSheetDiff, VersionControl};
+use crate::cell_ref::CellRef;
+use crate::spreadsheet::SpreadSheet;
+
+/// Uncommitted changes parked by `gitsap stash`
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub struct StashInfo {
+    /// HEAD when the changes were stashed
+    pub base: usize,
+    /// Hash of the object holding the changed cells
+    pub hash: String,
+    /// Number of changed cells
+    pub cells: usize,
+}
+
+impl VersionControl {
+    /// Fails if the working sheet has changes since HEAD that replacing it would lose
+    pub fn check_clean(&self, spreadsheet: &mut SpreadSheet) -> Result<(), VcsError> {
+        let changes = self.diff(None, None, spreadsheet)?;
+        if changes.is_empty() {
+            Ok(())
+        } else {
+            Err(VcsError::UncommittedChanges(changes.len()))
+        }
+    }
+
+    /// Parks the changes of the working sheet since HEAD and returns the sheet as of HEAD
+    pub fn stash(&mut self, spreadsheet: &mut SpreadSheet) -> Result<SpreadSheet, VcsError> {
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        let cells = self.get_diff_spread(
+            &mut CloneSpreadSheet::clone_spread(spreadsheet),
+            &self.spread_sheet,
+        );
+        if cells.is_empty() {
+            return Err(VcsError::NoChanges);
+        }
+        let _lock = self.lock()?;
+        let stash = SerialSheetDiff {
+            id: self.curr_commit,
+            cells,
+        };
+        let stash = StashInfo {
+            base: self.curr_commit,
+            hash: objects::write_object(&self.vcs_dir, &stash)?,
+            cells: stash.cells.len(),
+        };
+        self.transaction(|vcs| {
+            vcs.stashes.push(stash);
+            vcs.write_index()
+        })?;
+        Ok(self.head_spreadsheet())
+    }
+
+    /// Applies the most recently stashed changes to the working sheet and drops them.
+    ///
+    /// Fails without changing anything if a stashed cell was changed differently since it
+    /// was stashed.
+    pub fn stash_pop(&mut self, spreadsheet: &mut SpreadSheet) -> Result<SpreadSheet, VcsError> {
+        let stash = self.stashes.last().ok_or(VcsError::NoStash)?;
+        let cells = objects::read_object::<SerialSheetDiff>(&self.vcs_dir, &stash.hash)
+            .map_err(|_| VcsError::CorruptStash)?
+            .cells;
+        if !self.in_bounds(&cells) {
+            return Err(VcsError::CorruptStash);
+        }
+        let base = match stash.base {
+            0 => CloneSpreadSheet::new(self.m, self.n),
+            base => self.load_commit(base)?,
+        };
+        let mut working = CloneSpreadSheet::clone_spread(spreadsheet);
+        for cell in cells {
+            let (row, col) = (cell.row, cell.col);
+            let current = &working.cells[row][col];
+            if !current.compare(&base.cells[row][col]) && !current.compare(&cell) {
+                return Err(VcsError::StashConflict(CellRef::new(row, col)));
+            }
+            working.cells[row][col] = cell;
+        }
+        let _lock = self.lock()?;
+        self.transaction(|vcs| {
+            vcs.stashes.pop();
+            vcs.write_index()
+        })?;
+        Ok(working.build(self.m, self.n))
+    }
+
+    /// The stashed changes, newest first
+    pub fn list_stashes(&self) -> Vec<String> {
+        self.stashes
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, stash)| {
+                format!(
+                    "stash@{{{}}}: {} cells on commit {}",
+                    i, stash.cells, stash.base
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::SerialVcs;
+
+    #[test]
+    fn test_dirty_sheet_and_stash() {
+        let dir = TempRepo::new("stash");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("base", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.check_clean(&mut spreadsheet), Ok(()));
+        assert_eq!(vcs.stash(&mut spreadsheet).err(), Some(VcsError::NoChanges));
+
+        spreadsheet.set_value("A1", 2).unwrap();
+        spreadsheet.set_formula("B1", "A1*10").unwrap();
+        assert_eq!(
+            vcs.check_clean(&mut spreadsheet),
+            Err(VcsError::UncommittedChanges(2))
+        );
+        let mut spreadsheet = vcs.stash(&mut spreadsheet).unwrap();
+        assert_eq!(spreadsheet.get_cell_value(0, 0), Ok(Some(1)));
+        assert_eq!(vcs.check_clean(&mut spreadsheet), Ok(()));
+
+        // The stash survives reopening the repository
+        let mut vcs =
+            VersionControl::load(SerialVcs::load(&dir_name).unwrap(), dir_name.clone()).unwrap();
+        spreadsheet.set_value("A1", 3).unwrap();
+        assert_eq!(
+            vcs.stash_pop(&mut spreadsheet).err(),
+            Some(VcsError::StashConflict(CellRef::new(0, 0)))
+        );
+        spreadsheet.set_value("A1", 1).unwrap();
+        spreadsheet.set_value("A2", 5).unwrap();
+        let spreadsheet = vcs.stash_pop(&mut spreadsheet).unwrap();
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(20)));
+        assert_eq!(spreadsheet.get_cell_value(1, 0), Ok(Some(5)));
+        assert_eq!(
+            vcs.stash_pop(&mut SpreadSheet::new(2, 2)).err(),
+            Some(VcsError::NoStash)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use super::error::VcsError;
+use super::vcs_engine::{VersionControl, valid_name};
+
+impl VersionControl {
+    /// Names commit `id`, e.g. the version sent to finance. Tag names follow the rules for
+    /// branch names and cannot be used by a branch.
+    pub fn create_tag(&mut self, name: &str, id: usize) -> Result<(), VcsError> {
+        if !valid_name(name) {
+            return Err(VcsError::InvalidTagName(name.to_string()));
+        }
+        if self.tags.contains_key(name) {
+            return Err(VcsError::TagExists(name.to_string()));
+        }
+        if self.branches.contains_key(name) {
+            return Err(VcsError::BranchExists(name.to_string()));
+        }
+        if !self.map.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        self.transaction(|vcs| {
+            vcs.tags.insert(name.to_string(), id);
+            vcs.save()
+        })
+    }
+
+    pub fn delete_tag(&mut self, name: &str) -> Result<(), VcsError> {
+        if !self.tags.contains_key(name) {
+            return Err(VcsError::UnknownTag(name.to_string()));
+        }
+        self.transaction(|vcs| {
+            vcs.tags.remove(name);
+            vcs.save()
+        })
+    }
+
+    /// All tags with the commit they name and its message
+    pub fn list_tags(&self) -> Vec<String> {
+        self.tags
+            .iter()
+            .map(|(name, commit)| {
+                let message = self
+                    .map
+                    .get(commit)
+                    .map_or("", |info| info.message.as_str());
+                format!("{} (commit {}) {}", name, commit, message)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spreadsheet::SpreadSheet;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::SerialVcs;
+
+    #[test]
+    fn test_tags() {
+        let dir = TempRepo::new("tag");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        spreadsheet.set_value("A1", 1).unwrap();
+        vcs.commit("sent", &mut spreadsheet).unwrap();
+        vcs.create_tag("finance-v1", 1).unwrap();
+        spreadsheet.set_value("A1", 2).unwrap();
+        vcs.commit("later", &mut spreadsheet).unwrap();
+
+        assert_eq!(
+            vcs.create_tag("finance-v1", 2),
+            Err(VcsError::TagExists("finance-v1".to_string()))
+        );
+        assert_eq!(
+            vcs.create_tag("main", 2),
+            Err(VcsError::BranchExists("main".to_string()))
+        );
+        assert_eq!(
+            vcs.create_branch("finance-v1"),
+            Err(VcsError::TagExists("finance-v1".to_string()))
+        );
+        assert_eq!(
+            vcs.create_tag("42", 2),
+            Err(VcsError::InvalidTagName("42".to_string()))
+        );
+        assert_eq!(vcs.resolve_commit("finance-v1"), Ok(1));
+        assert_eq!(vcs.resolve_commit("main"), Ok(2));
+        assert_eq!(
+            vcs.resolve_commit("v2"),
+            Err(VcsError::UnknownRevision("v2".to_string()))
+        );
+
+        let vcs2 = VersionControl::load(SerialVcs::load(&dir_name).unwrap(), dir_name).unwrap();
+        assert_eq!(vcs2.tags()["finance-v1"], 1);
+        vcs.delete_tag("finance-v1").unwrap();
+        assert_eq!(
+            vcs.delete_tag("finance-v1"),
+            Err(VcsError::UnknownTag("finance-v1".to_string()))
+        );
+    }
+}
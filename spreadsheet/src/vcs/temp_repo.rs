@@ -0,0 +1,43 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory for a test repository, deleted with everything in it when dropped
+pub struct TempRepo {
+    path: PathBuf,
+}
+
+impl TempRepo {
+    /// `vcs_{name}_test_{pid}` in the system's temporary directory, emptied first in case an
+    /// earlier run was killed before cleaning up
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vcs_{}_test_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempRepo { path }
+    }
+
+    /// The directory as the string repositories are opened with
+    pub fn name(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+
+    /// `file` inside the directory, as a string
+    pub fn file(&self, file: &str) -> String {
+        self.path.join(file).to_string_lossy().to_string()
+    }
+}
+
+impl Deref for TempRepo {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+
+use super::error::VcsError;
+use super::vcs_engine::{CloneSpreadSheet, VersionControl};
+use crate::cell_ref::CellRef;
+use crate::spreadsheet::SpreadSheet;
+use crate::utils::Type;
+
+/// Value of a cell when it was committed, `None` for ERR
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub(super) struct RecordedValue {
+    row: usize,
+    col: usize,
+    value: Option<i32>,
+}
+
+/// A cell that computes a different value than when its commit was made, e.g. after a
+/// change to the formula engine. Values are `None` for ERR cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueMismatch {
+    pub cell: CellRef,
+    pub recorded: Option<i32>,
+    pub computed: Option<i32>,
+}
+
+impl fmt::Display for ValueMismatch {
+    /// e.g. `B1: committed 10, recomputed 12`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = |value: Option<i32>| value.map_or("ERR".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "{}: committed {}, recomputed {}",
+            self.cell,
+            value(self.recorded),
+            value(self.computed)
+        )
+    }
+}
+
+impl CloneSpreadSheet {
+    /// Values of the non-empty cells, read from the matching built spreadsheet
+    pub(super) fn values(&self, sheet: &SpreadSheet) -> Vec<RecordedValue> {
+        self.cells
+            .iter()
+            .flatten()
+            .filter(|cell| cell.t != Type::Nul)
+            .map(|cell| RecordedValue {
+                row: cell.row,
+                col: cell.col,
+                value: sheet.get_cell_value(cell.row, cell.col).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+impl VersionControl {
+    /// Cells of `spreadsheet` whose value differs from the value recorded in commit `id`.
+    /// Empty for commits made without `record_values`.
+    pub fn verify_values(
+        &self,
+        id: usize,
+        spreadsheet: &SpreadSheet,
+    ) -> Result<Vec<ValueMismatch>, VcsError> {
+        let values = self
+            .read_commit_object(id)?
+            .and_then(|object| object.values)
+            .unwrap_or_default();
+        Ok(values
+            .into_iter()
+            .filter_map(|recorded| {
+                let computed = spreadsheet
+                    .get_cell_value(recorded.row, recorded.col)
+                    .unwrap_or_default();
+                (computed != recorded.value).then(|| ValueMismatch {
+                    cell: CellRef::new(recorded.row, recorded.col),
+                    recorded: recorded.value,
+                    computed,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::temp_repo::TempRepo;
+
+    #[test]
+    fn test_recorded_values() {
+        let dir = TempRepo::new("values");
+        let mut vcs = VersionControl::new(dir.name(), &3, &3, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_value("A1", 5).unwrap();
+        vcs.commit("unrecorded", &mut spreadsheet).unwrap();
+        vcs.config.record_values = true;
+        spreadsheet.set_formula("B1", "A1*2").unwrap();
+        spreadsheet.set_formula("C1", "A1/0").unwrap();
+        vcs.commit("recorded", &mut spreadsheet).unwrap();
+
+        let restored = vcs.checkout(2).unwrap();
+        assert_eq!(vcs.verify_values(2, &restored), Ok(vec![]));
+
+        // As if the engine computed A1 differently than when the commit was made
+        let mut changed = vcs.checkout(2).unwrap();
+        changed.set_value("A1", 6).unwrap();
+        changed.set_value("C1", 1).unwrap();
+        let mismatches = vcs.verify_values(2, &changed).unwrap();
+        let lines: Vec<String> = mismatches.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "A1: committed 5, recomputed 6",
+                "B1: committed 10, recomputed 12",
+                "C1: committed ERR, recomputed 1"
+            ]
+        );
+        assert_eq!(vcs.verify_values(1, &changed), Ok(vec![]));
+    }
+}
@@ -1,37 +1,154 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs;
 use std::fs::File;
 use std::path::Path;
 
-use crate::spreadsheet::SpreadSheet;
-use crate::utils::Type;
+use super::bisect::BisectState;
+use super::config::VcsConfig;
+use super::error::VcsError;
+use super::lock::RepoLock;
+use super::merge::MergeState;
+use super::objects::{self, ObjectError};
+use super::stash::StashInfo;
+use super::values::RecordedValue;
+use crate::cell_ref::{CellRef, RangeRef};
+use crate::error::SheetError;
+use crate::formula::Formula;
+use crate::spreadsheet::{Operand, SpreadSheet};
+use crate::utils::{Status, Type};
 
+/// Branch that new repositories commit to
+const DEFAULT_BRANCH: &str = "main";
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+pub struct CommitInfo {
+    pub parent: usize,
+    pub message: String,
+    /// Second parent of a merge commit
+    #[serde(default)]
+    pub merge_parent: Option<usize>,
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Seconds since the Unix epoch
+    #[serde(default)]
+    pub timestamp: Option<i64>,
+    /// Whether the full sheet is stored in a snapshot
+    #[serde(default)]
+    pub snapshot: bool,
+    /// Hash of the commit object, `None` for commits stored in `commit_<id>.json`
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Hash of the snapshot object, if the commit has a snapshot
+    #[serde(default)]
+    pub snapshot_hash: Option<String>,
+}
+
+impl CommitInfo {
+    pub(super) fn new(parent: usize, message: &str) -> Self {
+        CommitInfo {
+            parent,
+            message: message.to_string(),
+            merge_parent: None,
+            author: None,
+            timestamp: None,
+            snapshot: false,
+            hash: None,
+            snapshot_hash: None,
+        }
+    }
+}
+
+/// A commit as stored in `objects/<hash>.json`. The hash covers the parents' hashes, so
+/// changing any commit changes the hashes of all commits built on it.
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+pub(super) struct CommitObject {
+    pub(super) parent: Option<String>,
+    pub(super) merge_parent: Option<String>,
+    pub(super) message: String,
+    pub(super) author: Option<String>,
+    pub(super) timestamp: Option<i64>,
+    pub(super) cells: Vec<SerialCell>,
+    /// Values of all non-empty cells, if `record_values` was set when committing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) values: Option<Vec<RecordedValue>>,
+}
+
+#[derive(Clone)]
 pub struct VersionControl {
-    map: HashMap<usize, (usize, String)>,
-    vcs_dir: String,
-    curr_commit: usize,
-    next_commit: usize,
-    spread_sheet: CloneSpreadSheet,
-    m: usize,
-    n: usize,
+    pub(super) map: HashMap<usize, CommitInfo>,
+    /// Branch name -> commit id of the branch tip
+    pub(super) branches: BTreeMap<String, usize>,
+    /// Branch that moves with new commits, `None` when HEAD is detached
+    pub(super) current_branch: Option<String>,
+    /// Tag name -> tagged commit id
+    pub(super) tags: BTreeMap<String, usize>,
+    /// Stashed changes, newest last
+    pub(super) stashes: Vec<StashInfo>,
+    /// Remote name -> directory of the repository
+    pub(super) remotes: BTreeMap<String, String>,
+    pub(super) bisect: Option<BisectState>,
+    pub(super) vcs_dir: String,
+    pub(super) curr_commit: usize,
+    pub(super) next_commit: usize,
+    pub(super) spread_sheet: CloneSpreadSheet,
+    pub(super) merge: Option<MergeState>,
+    pub(super) config: VcsConfig,
+    /// Why `config.json` could not be read, in which case commits are refused
+    pub(super) config_error: Option<VcsError>,
+    /// Hash of `vcs.json` as last read or written, to notice writes by other processes
+    index_hash: Option<String>,
+    pub(super) m: usize,
+    pub(super) n: usize,
+}
+
+fn default_branch() -> Option<String> {
+    Some(DEFAULT_BRANCH.to_string())
+}
+
+/// The repository's settings, or the defaults and the reason `config.json` is unusable
+fn load_config(vcs_dir: &str) -> (VcsConfig, Option<VcsError>) {
+    match VcsConfig::load(vcs_dir) {
+        Ok(config) => (config, None),
+        Err(err) => (VcsConfig::default(), Some(err)),
+    }
 }
 
 #[derive(Clone, serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct SerialVcs {
-    map: HashMap<usize, (usize, String)>,
+    map: HashMap<usize, CommitInfo>,
     m: usize,
     n: usize,
+    #[serde(default)]
+    branches: BTreeMap<String, usize>,
+    /// Commit id of HEAD
+    #[serde(default)]
+    pub(super) head: usize,
+    #[serde(default = "default_branch")]
+    pub(super) current_branch: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, usize>,
+    #[serde(default)]
+    stashes: Vec<StashInfo>,
+    #[serde(default)]
+    remotes: BTreeMap<String, String>,
+    #[serde(default)]
+    bisect: Option<BisectState>,
+    #[serde(default)]
+    merge: Option<MergeState>,
+    /// Hash of the `vcs.json` this was loaded from
+    #[serde(skip)]
+    hash: Option<String>,
 }
 
-#[derive(Clone, serde_derive::Serialize, serde_derive::Deserialize)]
+#[derive(Clone, Debug, serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct SerialCell {
-    row: usize,
-    col: usize,
+    pub(super) row: usize,
+    pub(super) col: usize,
     c1: Option<(usize, usize)>,
     c2: Option<(usize, usize)>,
     v1: Option<i32>,
     v2: Option<i32>,
-    t: Type,
+    pub(super) t: Type,
 }
 
 impl SerialCell {
@@ -44,53 +161,88 @@ impl SerialCell {
             && self.v2 == other.v2
             && self.t == other.t
     }
+
+    fn empty(row: usize, col: usize) -> Self {
+        SerialCell {
+            row,
+            col,
+            c1: None,
+            c2: None,
+            v1: None,
+            v2: None,
+            t: Type::Nul,
+        }
+    }
+
+    /// The cell's formula, or `None` for empty cells
+    pub fn formula(&self) -> Option<Formula> {
+        let operand = |c: Option<(usize, usize)>, v: Option<i32>| match (c, v) {
+            (Some(cell), _) => Some(Operand::Cell(CellRef::from(cell))),
+            (None, Some(value)) => Some(Operand::Constant(value)),
+            (None, None) => None,
+        };
+        match self.t {
+            Type::Nul => None,
+            Type::Min | Type::Max | Type::Sum | Type::Avg | Type::Dev => {
+                let range = RangeRef::new(CellRef::from(self.c1?), CellRef::from(self.c2?))?;
+                Some(Formula::Range { t: self.t, range })
+            }
+            t => {
+                // The engine stores a missing second operand as the constant 0
+                let operand_2 = match operand(self.c2, self.v2) {
+                    Some(Operand::Constant(0)) if matches!(t, Type::Add | Type::Sub) => None,
+                    operand_2 => operand_2,
+                };
+                Some(Formula::Operation {
+                    t,
+                    operand_1: operand(self.c1, self.v1)?,
+                    operand_2,
+                })
+            }
+        }
+    }
+
+    /// Writes the cell's equation into `spreadsheet`. Empty cells are set to 0.
+    pub(super) fn apply_to(&self, spreadsheet: &mut SpreadSheet) -> Result<Status, SheetError> {
+        if self.t == Type::Nul {
+            return spreadsheet._set_cell_value(self.row, self.col, 0);
+        }
+        // Older commits stored the value of cell operands alongside their coordinates
+        let v1 = if self.c1.is_some() { None } else { self.v1 };
+        let v2 = if self.c2.is_some() { None } else { self.v2 };
+        spreadsheet.set_cell_equation((self.row, self.col), self.c1, self.c2, v1, v2, self.t)
+    }
+
+    pub(super) fn describe(&self) -> String {
+        match self.formula() {
+            Some(formula) => formula.to_string(),
+            None => "(empty)".to_string(),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct CloneSpreadSheet {
-    cells: Vec<Vec<SerialCell>>,
+    pub(super) cells: Vec<Vec<SerialCell>>,
 }
 
 impl CloneSpreadSheet {
     pub fn new(m: usize, n: usize) -> Self {
-        let cells = vec![
-            vec![
-                SerialCell {
-                    row: 0,
-                    col: 0,
-                    c1: None,
-                    c2: None,
-                    v1: None,
-                    v2: None,
-                    t: Type::Nul
-                };
-                n
-            ];
-            m
-        ];
+        let cells = (0..m)
+            .map(|i| (0..n).map(|j| SerialCell::empty(i, j)).collect())
+            .collect();
         CloneSpreadSheet { cells }
     }
 
     pub fn clone_spread(spreadsheet: &mut SpreadSheet) -> Self {
         let m = spreadsheet.m;
         let n = spreadsheet.n;
-        let mut cells = vec![
-            vec![
-                SerialCell {
-                    row: 0,
-                    col: 0,
-                    c1: None,
-                    c2: None,
-                    v1: None,
-                    v2: None,
-                    t: Type::Nul
-                };
-                n
-            ];
-            m
-        ];
+        let mut cells = CloneSpreadSheet::new(m, n).cells;
         for (i, row) in cells.iter_mut().enumerate().take(m) {
             for (j, cell) in row.iter_mut().enumerate().take(n) {
-                let params = spreadsheet.get_cell_equation_parameters(i, j);
+                let Ok(params) = spreadsheet.get_cell_equation_parameters(i, j) else {
+                    continue;
+                };
                 let c1 = params.operand1_coordinates;
                 let c2 = params.operand2_coordinates;
                 let v1 = params.operand1_value;
@@ -109,32 +261,90 @@ impl CloneSpreadSheet {
         }
         CloneSpreadSheet { cells }
     }
+
+    /// Sheet of `m` rows and `n` columns with `cells` set, `None` if a cell lies outside it
+    pub(super) fn from_cells(m: usize, n: usize, cells: Vec<SerialCell>) -> Option<Self> {
+        let mut spread_sheet = CloneSpreadSheet::new(m, n);
+        for cell in cells {
+            let (row, col) = (cell.row, cell.col);
+            *spread_sheet.cells.get_mut(row)?.get_mut(col)? = cell;
+        }
+        Some(spread_sheet)
+    }
+
+    /// Cells that are not empty, in row-major order
+    pub(super) fn non_empty_cells(&self) -> Vec<SerialCell> {
+        self.cells
+            .iter()
+            .flatten()
+            .filter(|cell| cell.t != Type::Nul)
+            .cloned()
+            .collect()
+    }
+
+    /// Number of cells whose formula differs from `other`
+    pub(super) fn count_changes(&self, other: &CloneSpreadSheet) -> usize {
+        self.cells
+            .iter()
+            .flatten()
+            .zip(other.cells.iter().flatten())
+            .filter(|(cell, other)| !cell.compare(other))
+            .count()
+    }
+
+    /// Rebuilds a spreadsheet. Cells with malformed equations are left empty.
+    pub(super) fn build(&self, m: usize, n: usize) -> SpreadSheet {
+        let mut spreadsheet = SpreadSheet::new(m, n);
+        for cell in self.cells.iter().flatten() {
+            if cell.t != Type::Nul {
+                let _ = cell.apply_to(&mut spreadsheet);
+            }
+        }
+        spreadsheet
+    }
 }
 
 #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct SerialSheetDiff {
-    id: usize,
-    cells: Vec<SerialCell>,
+    pub(super) id: usize,
+    pub(super) cells: Vec<SerialCell>,
 }
 
 impl VersionControl {
-    pub fn new(vcs_dir: String, m: &usize, n: &usize) -> Self {
-        let mut map = HashMap::new();
+    /// Creates an empty repository in `vcs_dir`. An existing repository there is only
+    /// replaced if `force` is set, keeping its `config.json`.
+    pub fn new(vcs_dir: String, m: &usize, n: &usize, force: bool) -> Result<Self, VcsError> {
+        if Path::new(&format!("{}/vcs.json", vcs_dir)).exists() {
+            if !force {
+                return Err(VcsError::RepositoryExists(vcs_dir));
+            }
+            remove_repository(&vcs_dir);
+        }
+        let map = HashMap::new();
+        let (config, config_error) = load_config(&vcs_dir);
         let vcs_dir2 = vcs_dir.clone();
         if !Path::new(&vcs_dir2).exists() {
-            fs::create_dir(vcs_dir2).expect("Failed to create VCS directory");
-            // panic!("Failed to create VCS directory");
+            fs::create_dir(&vcs_dir2)?;
         }
-        map.insert(1, (0, "Init".to_string()));
-        VersionControl {
+        Ok(VersionControl {
             map,
+            branches: BTreeMap::new(),
+            current_branch: default_branch(),
+            tags: BTreeMap::new(),
+            stashes: Vec::new(),
+            remotes: BTreeMap::new(),
+            bisect: None,
             vcs_dir,
             curr_commit: 0,
             next_commit: 1,
             spread_sheet: CloneSpreadSheet::new(*m, *n),
+            merge: None,
+            config,
+            config_error,
+            index_hash: None,
             m: *m,
             n: *n,
-        }
+        })
     }
 
     pub fn dummy() -> Self {
@@ -143,112 +353,315 @@ impl VersionControl {
         let n = 1;
         VersionControl {
             map: HashMap::new(),
+            branches: BTreeMap::new(),
+            current_branch: None,
+            tags: BTreeMap::new(),
+            stashes: Vec::new(),
+            remotes: BTreeMap::new(),
+            bisect: None,
             vcs_dir,
             curr_commit: 0,
             next_commit: 1,
             spread_sheet: CloneSpreadSheet::new(m, n),
+            merge: None,
+            config: VcsConfig::default(),
+            config_error: None,
+            index_hash: None,
             m: 0,
             n: 0,
         }
     }
 
-    pub fn load(serial_vcs: SerialVcs, vcs_dir: String) -> Self {
+    /// Opens a saved repository at its HEAD. Use [`head_spreadsheet`](Self::head_spreadsheet)
+    /// to get the sheet as of HEAD. Fails if HEAD cannot be rebuilt.
+    ///
+    /// Deletes temporary files left by an interrupted write unless another process is
+    /// writing the repository.
+    pub fn load(serial_vcs: SerialVcs, vcs_dir: String) -> Result<Self, VcsError> {
+        if let Ok(_lock) = RepoLock::acquire(&vcs_dir) {
+            objects::remove_temp_files(&vcs_dir);
+        }
         let map = serial_vcs.map;
-        let commit_count = map.len();
-        VersionControl {
+        let next_commit = map.keys().max().map_or(1, |last| last + 1);
+        let (config, config_error) = load_config(&vcs_dir);
+        let head = if map.contains_key(&serial_vcs.head) {
+            serial_vcs.head
+        } else {
+            0
+        };
+        let mut vcs = VersionControl {
             map,
+            branches: serial_vcs.branches,
+            current_branch: serial_vcs.current_branch,
+            tags: serial_vcs.tags,
+            stashes: serial_vcs.stashes,
+            remotes: serial_vcs.remotes,
+            bisect: serial_vcs.bisect,
             vcs_dir,
-            curr_commit: 0,
-            next_commit: commit_count + 1,
+            curr_commit: head,
+            next_commit,
             m: serial_vcs.m,
             n: serial_vcs.n,
             spread_sheet: CloneSpreadSheet::new(serial_vcs.m, serial_vcs.n),
+            merge: serial_vcs.merge,
+            config,
+            config_error,
+            index_hash: serial_vcs.hash,
+        };
+        if head != 0 {
+            vcs.spread_sheet = vcs.load_commit(head)?;
         }
+        Ok(vcs)
     }
 
-    pub fn commit(&mut self, commit_msg: &str, spreadsheet: &mut SpreadSheet) {
-        let serial_sheet_diff = SerialSheetDiff {
-            id: self.next_commit,
-            cells: self.get_diff_spread(
-                &mut CloneSpreadSheet::clone_spread(spreadsheet),
-                &self.spread_sheet,
-            ),
-        };
+    /// Rereads the repository after another process wrote to it, taking over its HEAD. The
+    /// working sheet is left alone.
+    pub fn reload(&mut self) -> Result<(), VcsError> {
+        *self = VersionControl::load(SerialVcs::load(&self.vcs_dir)?, self.vcs_dir.clone())?;
+        Ok(())
+    }
+
+    /// The spreadsheet as of HEAD, or an empty one before the first commit
+    pub fn head_spreadsheet(&self) -> SpreadSheet {
+        self.spread_sheet.build(self.m, self.n)
+    }
+
+    /// Rebuilds the spreadsheet at commit `id` without moving HEAD
+    pub fn spreadsheet_at(&self, id: usize) -> Result<SpreadSheet, VcsError> {
+        if !self.map.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        Ok(self.load_commit(id)?.build(self.m, self.n))
+    }
+
+    pub fn current_branch(&self) -> Option<&str> {
+        self.current_branch.as_deref()
+    }
 
-        self.map
-            .insert(self.next_commit, (self.curr_commit, commit_msg.to_string()));
+    pub fn head(&self) -> usize {
+        self.curr_commit
+    }
 
-        let commit_path = format!("{}/commit_{}.json", self.vcs_dir, self.next_commit);
-        let file = File::create(&commit_path).expect("Failed to create commit file");
-        serde_json::to_writer(file, &serial_sheet_diff).expect("Failed to serialize commit");
+    pub fn commits(&self) -> &HashMap<usize, CommitInfo> {
+        &self.map
+    }
 
-        SerialVcs::save(self);
+    /// Branch names and the commit ids of their tips
+    pub fn branches(&self) -> &BTreeMap<String, usize> {
+        &self.branches
+    }
 
-        self.curr_commit = self.next_commit;
-        self.next_commit += 1;
-        self.spread_sheet = CloneSpreadSheet::clone_spread(spreadsheet);
+    /// Tag names and the commit ids they name
+    pub fn tags(&self) -> &BTreeMap<String, usize> {
+        &self.tags
     }
 
     pub fn list(&self) {
-        for (commit, (parent, message)) in &self.map {
-            println!(
+        let mut commits: Vec<_> = self.map.iter().collect();
+        commits.sort_unstable_by_key(|&(commit, _)| *commit);
+        for (commit, info) in commits {
+            print!(
                 "Commit ID: {}, Parent Commit: {}, Message: {}",
-                commit, parent, message
+                commit, info.parent, info.message
             );
+            match info.merge_parent {
+                Some(merged) => println!(", Merged Commit: {}", merged),
+                None => println!(),
+            }
         }
     }
 
-    pub fn checkout(&mut self, id: usize) -> SpreadSheet {
-        let vcs_dir = &self.vcs_dir;
-        self.spread_sheet = CloneSpreadSheet::new(self.m, self.n);
-        if !Path::new(vcs_dir).exists() {
-            panic!("VCS directory does not exist");
+    /// Resolves a commit id, branch name or tag name typed by the user to a commit id
+    pub fn resolve_commit(&self, revision: &str) -> Result<usize, VcsError> {
+        let Ok(id) = revision.parse::<usize>() else {
+            return self
+                .branches
+                .get(revision)
+                .or_else(|| self.tags.get(revision))
+                .copied()
+                .ok_or_else(|| VcsError::UnknownRevision(revision.to_string()));
+        };
+        if self.map.contains_key(&id) {
+            Ok(id)
+        } else {
+            Err(VcsError::UnknownCommit(id))
         }
+    }
 
-        // Create a parent order.
-        let mut commit_chain = Vec::new();
-        if !self.map.contains_key(&id) {
-            panic!("Commit id {} not found in VCS", id);
+    pub(super) fn parents(&self, id: usize) -> Vec<usize> {
+        match self.map.get(&id) {
+            Some(info) => [info.parent]
+                .into_iter()
+                .chain(info.merge_parent)
+                .filter(|&parent| parent != 0)
+                .collect(),
+            None => Vec::new(),
         }
-        commit_chain.push(id);
-        while *commit_chain.last().unwrap() != 1 {
-            let last = *commit_chain.last().unwrap();
-            let &(parent, _) = self.map.get(&last).unwrap_or_else(|| {
-                panic!("Commit {} not found in VCS while traversing parents", last)
-            });
-            if parent == 0 {
-                panic!("Encountered an invalid parent (0) for commit {}", last);
+    }
+
+    /// Commits reachable from `id` through first and merge parents, nearest first
+    pub(super) fn ancestors(&self, id: usize) -> Vec<usize> {
+        let mut seen = HashSet::from([id]);
+        let mut order = vec![];
+        let mut queue = VecDeque::from([id]);
+        while let Some(commit) = queue.pop_front() {
+            order.push(commit);
+            for parent in self.parents(commit) {
+                if seen.insert(parent) {
+                    queue.push_back(parent);
+                }
             }
-            commit_chain.push(parent);
         }
+        order
+    }
 
-        commit_chain.reverse();
-        for commit_id in commit_chain {
-            let commit_path = format!("{}/commit_{}.json", vcs_dir, commit_id);
-            let file = File::open(&commit_path).expect("Failed to open commit file");
-            let serial_sheet_diff: SerialSheetDiff =
-                serde_json::from_reader(file).expect("Failed to deserialize commit");
+    /// Nearest commit that both `a` and `b` descend from
+    pub fn common_ancestor(&self, a: usize, b: usize) -> Option<usize> {
+        let of_a: HashSet<usize> = self.ancestors(a).into_iter().collect();
+        self.ancestors(b)
+            .into_iter()
+            .find(|commit| of_a.contains(commit))
+    }
 
-            for cell in serial_sheet_diff.cells {
-                let (row, col) = (cell.row, cell.col);
-                self.spread_sheet.cells[row][col] = cell;
+    pub(super) fn commit_path(&self, id: usize) -> String {
+        format!("{}/commit_{}.json", self.vcs_dir, id)
+    }
+
+    /// Hash of the object of commit `id`, `None` for the root's parent and legacy commits
+    pub(super) fn object_hash(&self, id: usize) -> Option<String> {
+        self.map.get(&id).and_then(|info| info.hash.clone())
+    }
+
+    /// Whether every cell lies inside the sheet
+    pub(super) fn in_bounds(&self, cells: &[SerialCell]) -> bool {
+        cells
+            .iter()
+            .all(|cell| cell.row < self.m && cell.col < self.n)
+    }
+
+    /// The object of commit `id`, checked against its hash and recorded parents. `None` for
+    /// legacy commits stored in `commit_<id>.json`.
+    pub(super) fn read_commit_object(&self, id: usize) -> Result<Option<CommitObject>, VcsError> {
+        let info = self.map.get(&id).ok_or(VcsError::UnknownCommit(id))?;
+        let Some(hash) = &info.hash else {
+            return Ok(None);
+        };
+        let object: CommitObject =
+            objects::read_object(&self.vcs_dir, hash).map_err(|err| match err {
+                ObjectError::Missing => VcsError::MissingCommit(id),
+                ObjectError::Corrupt => VcsError::CorruptCommit(id),
+            })?;
+        let matches_info = object.parent == self.object_hash(info.parent)
+            && object.merge_parent == info.merge_parent.and_then(|p| self.object_hash(p))
+            && object.message == info.message
+            && object.author == info.author
+            && object.timestamp == info.timestamp;
+        if !matches_info {
+            return Err(VcsError::CorruptCommit(id));
+        }
+        Ok(Some(object))
+    }
+
+    /// Cells changed by commit `id`, checked against its hash and recorded parents
+    pub(super) fn read_diff(&self, id: usize) -> Result<Vec<SerialCell>, VcsError> {
+        let cells = match self.read_commit_object(id)? {
+            Some(object) => object.cells,
+            None => {
+                let file =
+                    File::open(self.commit_path(id)).map_err(|_| VcsError::MissingCommit(id))?;
+                let diff: SerialSheetDiff =
+                    serde_json::from_reader(file).map_err(|_| VcsError::CorruptCommit(id))?;
+                diff.cells
             }
+        };
+        if !self.in_bounds(&cells) {
+            return Err(VcsError::CorruptCommit(id));
         }
+        Ok(cells)
+    }
 
-        let mut spreadsheet = SpreadSheet::new(self.m, self.n);
-        for i in 0..self.m {
-            for j in 0..self.n {
-                let cell = &self.spread_sheet.cells[i][j];
-                let (c1, c2, v1, v2, t) = (cell.c1, cell.c2, cell.v1, cell.v2, cell.t);
-                if t == Type::Nul || (c1.is_none() ^ v1.is_none()) || (c2.is_none() ^ v2.is_none())
-                {
-                    continue;
-                }
-                spreadsheet.set_cell_equation((i, j), c1, c2, v1, v2, t);
+    /// Rebuilds commit `id` from its nearest snapshot ancestor and the diffs after it,
+    /// verifying each object read
+    pub(super) fn load_commit(&self, id: usize) -> Result<CloneSpreadSheet, VcsError> {
+        let mut spread_sheet = CloneSpreadSheet::new(self.m, self.n);
+        let (snapshot, commit_chain) = self.diffs_since_snapshot(id)?;
+        if let Some(snapshot) = snapshot {
+            for cell in self.read_snapshot(snapshot)? {
+                let (row, col) = (cell.row, cell.col);
+                spread_sheet.cells[row][col] = cell;
             }
         }
+        for commit in commit_chain {
+            for cell in self.read_diff(commit)? {
+                let (row, col) = (cell.row, cell.col);
+                spread_sheet.cells[row][col] = cell;
+            }
+        }
+        Ok(spread_sheet)
+    }
 
-        spreadsheet
+    /// Rebuilds the spreadsheet at commit `id` and detaches HEAD from the current branch.
+    /// Refuses to run during a merge, which [`reset`](Self::reset) abandons instead.
+    pub fn checkout(&mut self, id: usize) -> Result<SpreadSheet, VcsError> {
+        if self.merge.is_some() {
+            return Err(VcsError::MergeInProgress);
+        }
+        self.transaction(|vcs| vcs.move_head(id, None))
+    }
+
+    /// Rebuilds the spreadsheet at commit `id` and makes it HEAD of `branch`, or detached
+    /// HEAD if `None`, then saves
+    pub(super) fn move_head(
+        &mut self,
+        id: usize,
+        branch: Option<String>,
+    ) -> Result<SpreadSheet, VcsError> {
+        if !self.map.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        self.spread_sheet = self.load_commit(id)?;
+        self.curr_commit = id;
+        self.current_branch = branch;
+        self.save()?;
+        Ok(self.head_spreadsheet())
+    }
+
+    /// Takes the repository lock, failing if another process holds it or has written
+    /// `vcs.json` since this one last read or wrote it
+    pub(super) fn lock(&self) -> Result<RepoLock, VcsError> {
+        let lock = RepoLock::acquire(&self.vcs_dir)?;
+        let on_disk = fs::read(SerialVcs::path(&self.vcs_dir))
+            .ok()
+            .map(|bytes| objects::hash(&bytes));
+        if on_disk != self.index_hash {
+            return Err(VcsError::RepositoryChanged);
+        }
+        Ok(lock)
+    }
+
+    /// Replaces `vcs.json`, the repository lock has to be held
+    pub(super) fn write_index(&mut self) -> Result<(), VcsError> {
+        self.index_hash = Some(SerialVcs::save(self)?);
+        Ok(())
+    }
+
+    /// Saves HEAD, branches and commits to `vcs.json`
+    pub(super) fn save(&mut self) -> Result<(), VcsError> {
+        let _lock = self.lock()?;
+        self.write_index()
+    }
+
+    /// Runs `change` on a copy of the repository and keeps the copy only if `change`
+    /// succeeds, which includes writing `vcs.json`. If a write fails, the repository stays
+    /// as it is on disk instead of going on from changes that were never saved.
+    pub(super) fn transaction<T>(
+        &mut self,
+        change: impl FnOnce(&mut VersionControl) -> Result<T, VcsError>,
+    ) -> Result<T, VcsError> {
+        let mut copy = self.clone();
+        let result = change(&mut copy)?;
+        *self = copy;
+        Ok(result)
     }
 
     pub fn get_diff_spread(
@@ -273,6 +686,34 @@ impl VersionControl {
     pub fn get_m_n(&self) -> (usize, usize) {
         (self.m, self.n)
     }
+
+    pub fn vcs_dir(&self) -> &str {
+        &self.vcs_dir
+    }
+}
+
+/// Branch and tag names are letters, digits, `-` and `_`, and cannot be all digits so they
+/// are never mistaken for commit ids
+pub(super) fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        && !name.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Deletes the history of the repository in `vcs_dir`, leaving other files alone
+pub(super) fn remove_repository(vcs_dir: &str) {
+    let _ = fs::remove_file(format!("{}/vcs.json", vcs_dir));
+    let _ = fs::remove_dir_all(format!("{}/objects", vcs_dir));
+    if let Ok(entries) = fs::read_dir(vcs_dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("commit_") && name.ends_with(".json") {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
 }
 
 impl SerialVcs {
@@ -282,36 +723,188 @@ impl SerialVcs {
             map: vcs.map.clone(),
             m: vcs.m,
             n: vcs.n,
+            branches: vcs.branches.clone(),
+            head: vcs.curr_commit,
+            current_branch: vcs.current_branch.clone(),
+            tags: vcs.tags.clone(),
+            stashes: vcs.stashes.clone(),
+            remotes: vcs.remotes.clone(),
+            bisect: vcs.bisect.clone(),
+            merge: vcs.merge.clone(),
+            hash: None,
         }
     }
-    pub fn save(vcs: &VersionControl) {
-        // used when saved
-        let vcs_dir = &vcs.vcs_dir;
-        let serial_vcs = SerialVcs::new(vcs);
 
-        let vcs_path = format!("{}/vcs.json", vcs_dir);
-        let file = File::create(&vcs_path).expect("Failed to create VCS file");
-        serde_json::to_writer(file, &serial_vcs).expect("Failed to serialize VCS");
+    pub(super) fn path(vcs_dir: &str) -> String {
+        format!("{}/vcs.json", vcs_dir)
+    }
+
+    /// Rows and columns of the sheet
+    pub(super) fn dimensions(&self) -> (usize, usize) {
+        (self.m, self.n)
     }
-    pub fn load(vcs_dir: &str) -> SerialVcs {
-        let vcs_path = format!("{}/vcs.json", vcs_dir);
-        let file = File::open(&vcs_path).expect("Failed to open VCS file");
-        let serial_vcs: SerialVcs =
-            serde_json::from_reader(file).expect("Failed to deserialize VCS");
-        serial_vcs
+
+    /// Atomically replaces `vcs.json` and returns the hash of what was written
+    pub fn save(vcs: &VersionControl) -> Result<String, VcsError> {
+        let bytes = serde_json::to_vec(&SerialVcs::new(vcs))
+            .map_err(|err| VcsError::Io(err.to_string()))?;
+        objects::write_atomic(&SerialVcs::path(&vcs.vcs_dir), &bytes)?;
+        Ok(objects::hash(&bytes))
+    }
+
+    pub fn load(vcs_dir: &str) -> Result<SerialVcs, VcsError> {
+        let vcs_path = SerialVcs::path(vcs_dir);
+        let bytes = fs::read(&vcs_path).map_err(|_| VcsError::MissingIndex(vcs_dir.to_string()))?;
+        let mut serial_vcs: SerialVcs =
+            serde_json::from_slice(&bytes).map_err(|_| VcsError::CorruptIndex(vcs_path))?;
+        serial_vcs.hash = Some(objects::hash(&bytes));
+        Ok(serial_vcs)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vcs::temp_repo::TempRepo;
 
     #[test]
     fn test_version_control_new() {
-        let vcs = VersionControl::new("./vcs_test".to_string(), &10, &10);
+        let dir = TempRepo::new("new");
+        let vcs_dir = dir.join("vcs_dir");
+        let vcs =
+            VersionControl::new(vcs_dir.to_string_lossy().to_string(), &10, &10, false).unwrap();
         assert_eq!(vcs.get_m_n(), (10, 10));
         assert_eq!(vcs.curr_commit, 0);
         assert_eq!(vcs.next_commit, 1);
-        assert!(Path::new("./vcs_test").exists());
+        assert!(vcs_dir.exists());
+    }
+
+    #[test]
+    fn test_commit_and_checkout() {
+        let dir = TempRepo::new("checkout");
+        let mut vcs = VersionControl::new(dir.name(), &3, &3, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        vcs.commit("Initial_commit", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 5).unwrap();
+        spreadsheet.set_formula("B1", "A1*2").unwrap();
+        vcs.commit("first", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 7).unwrap();
+        vcs.commit("second", &mut spreadsheet).unwrap();
+
+        let restored = vcs.checkout(2).unwrap();
+        assert_eq!(restored.get_cell_value(0, 0), Ok(Some(5)));
+        assert_eq!(restored.get_cell_value(0, 1), Ok(Some(10)));
+        assert_eq!(vcs.checkout(9).err(), Some(VcsError::UnknownCommit(9)));
+        let restored = vcs.checkout(3).unwrap();
+        assert_eq!(restored.get_cell_value(0, 1), Ok(Some(14)));
+    }
+
+    #[test]
+    fn test_serial_cell_formula() {
+        let mut spreadsheet = SpreadSheet::new(3, 3);
+        spreadsheet.set_formula("A1", "SUM(B1:C2)").unwrap();
+        spreadsheet.set_formula("A2", "B1-4").unwrap();
+        let clone = CloneSpreadSheet::clone_spread(&mut spreadsheet);
+        assert_eq!(clone.cells[0][0].describe(), "SUM(B1:C2)");
+        assert_eq!(clone.cells[1][0].describe(), "B1-4");
+        assert_eq!(clone.cells[2][2].describe(), "(empty)");
+    }
+
+    #[test]
+    fn test_load_restores_head() {
+        let dir = TempRepo::new("load");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        vcs.commit("Initial_commit", &mut spreadsheet).unwrap();
+        spreadsheet.set_value("A1", 3).unwrap();
+        spreadsheet.set_formula("B1", "A1+1").unwrap();
+        vcs.commit("first", &mut spreadsheet).unwrap();
+
+        let mut vcs =
+            VersionControl::load(SerialVcs::load(&dir_name).unwrap(), dir_name.clone()).unwrap();
+        assert_eq!(vcs.head(), 2);
+        let mut spreadsheet = vcs.head_spreadsheet();
+        assert_eq!(spreadsheet.get_cell_value(0, 1), Ok(Some(4)));
+        assert_eq!(vcs.diff(None, None, &mut spreadsheet), Ok(vec![]));
+
+        spreadsheet.set_value("A1", 5).unwrap();
+        vcs.commit("second", &mut spreadsheet).unwrap();
+        assert_eq!(vcs.commits()[&3].parent, 2);
+        assert_eq!(vcs.branches()["main"], 3);
+    }
+
+    #[test]
+    fn test_new_refuses_existing_repository() {
+        let dir = TempRepo::new("init");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        vcs.commit("Initial_commit", &mut SpreadSheet::new(2, 2))
+            .unwrap();
+        fs::write(dir.join("config.json"), r#"{"author": "alice"}"#).unwrap();
+
+        assert_eq!(
+            VersionControl::new(dir_name.clone(), &2, &2, false).err(),
+            Some(VcsError::RepositoryExists(dir_name.clone()))
+        );
+        assert_eq!(SerialVcs::load(&dir_name).unwrap().map.len(), 1);
+
+        let vcs = VersionControl::new(dir_name.clone(), &3, &3, true).unwrap();
+        assert_eq!(vcs.get_m_n(), (3, 3));
+        assert!(!dir.join("vcs.json").exists() && !dir.join("objects").exists());
+        assert_eq!(vcs.config.author(), "alice");
+
+        // A malformed config is reported instead of silently falling back to the defaults
+        fs::write(dir.join("config.json"), r#"{"author": "#).unwrap();
+        let mut vcs = VersionControl::new(dir_name.clone(), &3, &3, true).unwrap();
+        let config_path = format!("{}/config.json", dir_name);
+        assert!(matches!(
+            vcs.commit("Initial_commit", &mut SpreadSheet::new(3, 3)),
+            Err(VcsError::InvalidConfig(path, _)) if path == config_path
+        ));
+        assert_eq!(vcs.head(), 0);
+    }
+
+    #[test]
+    fn test_concurrent_writers_and_interrupted_writes() {
+        let dir = TempRepo::new("crash");
+        let dir_name = dir.name();
+        let mut vcs = VersionControl::new(dir_name.clone(), &2, &2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        vcs.commit("Initial_commit", &mut spreadsheet).unwrap();
+
+        // A second session commits first, so this one has to reload the repository
+        let mut other =
+            VersionControl::load(SerialVcs::load(&dir_name).unwrap(), dir_name.clone()).unwrap();
+        spreadsheet.set_value("A1", 1).unwrap();
+        other.commit("other", &mut spreadsheet).unwrap();
+        assert_eq!(
+            vcs.commit("mine", &mut spreadsheet),
+            Err(VcsError::RepositoryChanged)
+        );
+        assert!(!vcs.map.contains_key(&2));
+        vcs.reload().unwrap();
+        assert_eq!(vcs.head(), 2);
+        vcs.commit("mine", &mut spreadsheet).unwrap();
+
+        let lock = RepoLock::acquire(&dir_name).unwrap();
+        assert!(matches!(
+            other.commit("locked", &mut spreadsheet),
+            Err(VcsError::Locked(_, Some(_)))
+        ));
+        drop(lock);
+
+        // A crash before the rename leaves the old index and a temporary file behind
+        fs::write(dir.join("vcs.json.tmp"), "{\"map\":").unwrap();
+        let vcs =
+            VersionControl::load(SerialVcs::load(&dir_name).unwrap(), dir_name.clone()).unwrap();
+        assert_eq!(vcs.head(), 3);
+        assert!(!dir.join("vcs.json.tmp").exists());
+
+        fs::write(dir.join("vcs.json"), "{\"map\":").unwrap();
+        assert_eq!(
+            SerialVcs::load(&dir_name).err(),
+            Some(VcsError::CorruptIndex(format!("{}/vcs.json", dir_name)))
+        );
     }
 }
@@ -1,101 +1,297 @@
+use super::backend::{HistoryEntry, VcsBackend};
+use super::config::VcsConfig;
+use super::error::VcsError;
+use super::lock::RepoLock;
+use super::objects;
+use super::vcs_engine::{CloneSpreadSheet, SerialCell};
 use crate::spreadsheet::SpreadSheet;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::path::Path;
 
+/// A commit of the snapshot backend, stored in `commit_<id>.json` with every non-empty cell
 #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct Commit {
-    id: u32,
+    id: usize,
     message: String,
-    parent: Option<u32>,
-    // delta: Option<Delta>,
-    spreadsheet: SpreadSheet, // Store the spreadsheet state
-    // spread_dist: u32, // Track the last full spreadsheet state
+    parent: Option<usize>,
+    cells: Vec<SerialCell>,
     user: String, // Track the user who made the commit
+    /// Seconds since the Unix epoch
+    timestamp: i64,
 }
 
-// #[derive(Serialize, Deserialize, Clone)]
-// pub struct Delta {
-//     pub cells: HashMap<(u32,u32), String>,
-// }
+/// What `snapshots.json` records about a commit
+#[derive(Clone, Debug, serde_derive::Serialize, serde_derive::Deserialize)]
+struct CommitSummary {
+    /// 0 for the first commit
+    parent: usize,
+    message: String,
+    user: String,
+}
+
+/// Index of a snapshot repository, stored in `snapshots.json`
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct SnapshotIndex {
+    m: usize,
+    n: usize,
+    commits_with_parent: BTreeMap<usize, CommitSummary>,
+    head: usize,
+}
 
+/// Version control that stores the full sheet in every commit. Checking out reads a single
+/// file, at the cost of disk space. There are no branches: a commit on top of an older
+/// commit starts a new line of history.
 pub struct VersionControlSystem {
-    commits_with_parent: HashMap<u32, (u32, String)>, // List of commit IDs
-    current_id: u32,
-    next_commit_id: u32,
+    commits_with_parent: BTreeMap<usize, CommitSummary>,
+    current_id: usize,
+    next_commit_id: usize,
     vcs_dir: String,
-    // user_manager: UserManager,
-    // current_user: Option<String>, // Track the logged-in user
+    /// The sheet as of `current_id`
+    spread_sheet: CloneSpreadSheet,
+    m: usize,
+    n: usize,
 }
 
 impl VersionControlSystem {
-    pub fn new(vcs_dir: &str) -> Self {
-        if !Path::new(vcs_dir).exists() {
-            fs::create_dir(vcs_dir).expect("Failed to create VCS directory");
-            // panic!("Failed to create VCS directory");
+    /// Creates an empty repository in `vcs_dir`. A repository of either backend there is
+    /// only replaced if `force` is set.
+    pub fn new(vcs_dir: String, m: usize, n: usize, force: bool) -> Result<Self, VcsError> {
+        let diff_index = format!("{}/vcs.json", vcs_dir);
+        if Path::new(&index_path(&vcs_dir)).exists() || Path::new(&diff_index).exists() {
+            if !force {
+                return Err(VcsError::RepositoryExists(vcs_dir));
+            }
+            super::vcs_engine::remove_repository(&vcs_dir);
+            let _ = fs::remove_file(index_path(&vcs_dir));
+        }
+        if !Path::new(&vcs_dir).exists() {
+            fs::create_dir_all(&vcs_dir)?;
         }
 
-        VersionControlSystem {
-            commits_with_parent: HashMap::new(),
+        Ok(VersionControlSystem {
+            commits_with_parent: BTreeMap::new(),
             current_id: 0,
             next_commit_id: 1,
-            vcs_dir: vcs_dir.to_string(),
+            vcs_dir,
+            spread_sheet: CloneSpreadSheet::new(m, n),
+            m,
+            n,
+        })
+    }
+
+    /// Opens the repository in `vcs_dir` at its HEAD
+    pub fn load(vcs_dir: String) -> Result<Self, VcsError> {
+        let path = index_path(&vcs_dir);
+        let file = File::open(&path).map_err(|_| VcsError::MissingIndex(vcs_dir.clone()))?;
+        let index: SnapshotIndex =
+            serde_json::from_reader(file).map_err(|_| VcsError::CorruptIndex(path))?;
+        let mut vcs = VersionControlSystem {
+            next_commit_id: index
+                .commits_with_parent
+                .keys()
+                .max()
+                .map_or(1, |id| id + 1),
+            commits_with_parent: index.commits_with_parent,
+            current_id: index.head,
+            vcs_dir,
+            spread_sheet: CloneSpreadSheet::new(index.m, index.n),
+            m: index.m,
+            n: index.n,
+        };
+        if vcs.current_id != 0 {
+            vcs.spread_sheet = vcs.read(vcs.current_id)?;
         }
+        Ok(vcs)
     }
 
-    pub fn commit(&mut self, spreadsheet: &SpreadSheet, message: &str) {
-        println!("Committing changes...");
-        // Check if the commit message is empty
+    fn commit_path(&self, id: usize) -> String {
+        format!("{}/commit_{}.json", self.vcs_dir, id)
+    }
+
+    /// The sheet stored in commit `id`
+    fn read(&self, id: usize) -> Result<CloneSpreadSheet, VcsError> {
+        if !self.commits_with_parent.contains_key(&id) {
+            return Err(VcsError::UnknownCommit(id));
+        }
+        let file = File::open(self.commit_path(id)).map_err(|_| VcsError::MissingCommit(id))?;
+        let commit: Commit =
+            serde_json::from_reader(file).map_err(|_| VcsError::CorruptCommit(id))?;
+        if commit.id != id {
+            return Err(VcsError::CorruptCommit(id));
+        }
+        CloneSpreadSheet::from_cells(self.m, self.n, commit.cells)
+            .ok_or(VcsError::CorruptCommit(id))
+    }
+
+    /// Replaces `snapshots.json`, the repository lock has to be held
+    fn write_index(&self) -> Result<(), VcsError> {
+        let index = SnapshotIndex {
+            m: self.m,
+            n: self.n,
+            commits_with_parent: self.commits_with_parent.clone(),
+            head: self.current_id,
+        };
+        let bytes = serde_json::to_vec(&index).map_err(|err| VcsError::Io(err.to_string()))?;
+        objects::write_atomic(&index_path(&self.vcs_dir), &bytes)?;
+        Ok(())
+    }
+}
+
+impl VcsBackend for VersionControlSystem {
+    fn commit(
+        &mut self,
+        message: &str,
+        author: Option<&str>,
+        spreadsheet: &mut SpreadSheet,
+    ) -> Result<Vec<String>, VcsError> {
+        if message.is_empty() {
+            return Err(VcsError::EmptyMessage);
+        }
+        let config = VcsConfig::load(&self.vcs_dir)?;
+        let _lock = RepoLock::acquire(&self.vcs_dir)?;
+        let working = CloneSpreadSheet::clone_spread(spreadsheet);
         let commit = Commit {
             id: self.next_commit_id,
             message: message.to_string(),
-            parent: Some(self.current_id),
-            spreadsheet: spreadsheet.clone(),
-            user: String::from("user"),
+            parent: Some(self.current_id).filter(|&parent| parent != 0),
+            cells: working.non_empty_cells(),
+            user: author.map_or_else(|| config.author(), str::to_string),
+            timestamp: chrono::Utc::now().timestamp(),
         };
-        let commit_path = format!("{}/commit_{}.json", self.vcs_dir, self.next_commit_id);
-        let file = File::create(&commit_path).expect("Failed to create commit file");
-        serde_json::to_writer(file, &commit).expect("Failed to serialize commit");
+        let bytes = serde_json::to_vec(&commit).map_err(|err| VcsError::Io(err.to_string()))?;
+        objects::write_atomic(&self.commit_path(commit.id), &bytes)?;
 
-        self.commits_with_parent
-            .insert(self.next_commit_id, (self.current_id, commit.message));
-
-        println!(
-            "Commit created with ID: {} by user: {}",
-            self.current_id,
-            commit.user,
-            // self.current_user.clone().unwrap()
+        self.commits_with_parent.insert(
+            commit.id,
+            CommitSummary {
+                parent: self.current_id,
+                message: commit.message,
+                user: commit.user,
+            },
         );
         self.current_id = self.next_commit_id;
         self.next_commit_id += 1;
+        self.spread_sheet = working;
+        self.write_index()?;
+        Ok(vec![])
     }
 
-    pub fn list(&self) {
-        for commit in self.commits_with_parent.keys() {
-            let commit_ = self.commits_with_parent.get(commit).unwrap();
+    fn checkout(&mut self, id: usize) -> Result<SpreadSheet, VcsError> {
+        let spread_sheet = self.read(id)?;
+        let _lock = RepoLock::acquire(&self.vcs_dir)?;
+        self.current_id = id;
+        self.spread_sheet = spread_sheet;
+        self.write_index()?;
+        Ok(self.head_spreadsheet())
+    }
+
+    /// Same as checkout, there are no branches to move
+    fn reset(&mut self, id: usize) -> Result<SpreadSheet, VcsError> {
+        self.checkout(id)
+    }
+
+    fn list(&self) {
+        for (id, commit) in &self.commits_with_parent {
             println!(
-                "ID: {}, Message: {}, Parent: {:?}",
-                commit, commit_.1, commit_.0
+                "Commit ID: {}, Parent Commit: {}, Message: {}",
+                id, commit.parent, commit.message
             );
         }
     }
 
-    pub fn checkout(&mut self, branch_id: u32, spreadsheet: &SpreadSheet) -> SpreadSheet {
-        if let Some(_commit) = self.commits_with_parent.get(&branch_id) {
-            self.current_id = branch_id;
-            println!("Checked out to branch ID: {}", branch_id);
-            self.reconstruct_spreadsheet(branch_id)
-        } else {
-            println!("Branch ID {} does not exist", branch_id);
-            spreadsheet.clone()
+    fn history(&self) -> Vec<HistoryEntry> {
+        self.commits_with_parent
+            .iter()
+            .map(|(&id, commit)| HistoryEntry {
+                id,
+                parent: commit.parent,
+                message: commit.message.clone(),
+                author: Some(commit.user.clone()),
+            })
+            .collect()
+    }
+
+    fn spreadsheet_at(&self, id: usize) -> Result<SpreadSheet, VcsError> {
+        Ok(self.read(id)?.build(self.m, self.n))
+    }
+
+    fn head(&self) -> usize {
+        self.current_id
+    }
+
+    fn head_spreadsheet(&self) -> SpreadSheet {
+        self.spread_sheet.build(self.m, self.n)
+    }
+
+    fn get_m_n(&self) -> (usize, usize) {
+        (self.m, self.n)
+    }
+
+    fn check_clean(&self, spreadsheet: &mut SpreadSheet) -> Result<(), VcsError> {
+        match CloneSpreadSheet::clone_spread(spreadsheet).count_changes(&self.spread_sheet) {
+            0 => Ok(()),
+            changes => Err(VcsError::UncommittedChanges(changes)),
         }
     }
+}
+
+fn index_path(vcs_dir: &str) -> String {
+    format!("{}/snapshots.json", vcs_dir)
+}
 
-    fn reconstruct_spreadsheet(&self, commit_id: u32) -> SpreadSheet {
-        let commit_path = format!("{}/commit_{}.json", self.vcs_dir, commit_id);
-        let file = File::open(&commit_path).expect("Failed to open commit file");
-        let commit: Commit = serde_json::from_reader(file).expect("Failed to deserialize commit");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcs::backend::migrate;
+    use crate::vcs::temp_repo::TempRepo;
+    use crate::vcs::vcs_engine::VersionControl;
 
-        commit.spreadsheet.clone()
+    #[test]
+    fn test_snapshot_backend_and_migration() {
+        let dir = TempRepo::new("backend");
+        let mut vcs = VersionControlSystem::new(dir.file("snapshot"), 2, 2, false).unwrap();
+        let mut spreadsheet = SpreadSheet::new(2, 2);
+        vcs.commit("Initial_commit", None, &mut spreadsheet)
+            .unwrap();
+        spreadsheet.set_value("A1", 4).unwrap();
+        spreadsheet.set_formula("B1", "A1+1").unwrap();
+        vcs.commit("first", Some("ada"), &mut spreadsheet).unwrap();
+        assert_eq!(vcs.check_clean(&mut spreadsheet), Ok(()));
+        spreadsheet.set_value("A1", 6).unwrap();
+        assert_eq!(
+            vcs.check_clean(&mut spreadsheet),
+            Err(VcsError::UncommittedChanges(1))
+        );
+
+        // A commit on top of an older one starts a second line of history
+        vcs.checkout(1).unwrap();
+        spreadsheet = vcs.head_spreadsheet();
+        spreadsheet.set_value("A2", 9).unwrap();
+        vcs.commit("other", None, &mut spreadsheet).unwrap();
+        let vcs = VersionControlSystem::load(dir.file("snapshot")).unwrap();
+        assert_eq!(vcs.head(), 3);
+        assert_eq!(vcs.history()[2].parent, 1);
+        let restored = vcs.spreadsheet_at(2).unwrap();
+        assert_eq!(restored.get_cell_value(0, 1), Ok(Some(5)));
+
+        let mut diff = VersionControl::new(dir.file("diff"), &2, &2, false).unwrap();
+        assert_eq!(migrate(&vcs, &mut diff), Ok(3));
+        assert_eq!(VcsBackend::history(&diff), vcs.history());
+        assert_eq!(diff.head(), 3);
+        assert_eq!(diff.branches()["main"], 3);
+        assert_eq!(diff.head_spreadsheet().get_cell_value(1, 0), Ok(Some(9)));
+
+        let mut back = VersionControlSystem::new(dir.file("back"), 2, 2, false).unwrap();
+        assert_eq!(migrate(&diff, &mut back), Ok(3));
+        assert_eq!(back.history(), vcs.history());
+        assert_eq!(
+            back.spreadsheet_at(2).unwrap().get_cell_value(0, 1),
+            Ok(Some(5))
+        );
+        assert!(matches!(
+            VersionControlSystem::new(dir.file("diff"), 2, 2, false),
+            Err(VcsError::RepositoryExists(_))
+        ));
     }
 }